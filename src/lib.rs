@@ -1,4 +1,6 @@
 pub mod clap;
+#[cfg(feature = "http-client")]
+pub mod http_client;
 pub mod migration;
 pub mod otel;
 pub mod rocket;
@@ -9,12 +9,12 @@ pub async fn create_db(database_url: &str) {
     let database_url = url.clone();
     let database = database_url
         .path_segments()
-        .and_then(|mut segments| segments.nth(0))
+        .and_then(|mut segments| segments.next())
         .expect("database name");
 
     url.set_path("postgres");
 
-    let options = PgConnectOptions::from_str(&url.to_string())
+    let options = PgConnectOptions::from_str(url.as_ref())
         .expect("valid db connection string")
         .to_owned();
 
@@ -0,0 +1,260 @@
+use std::borrow::Cow;
+use std::sync::{Arc, Mutex};
+
+use figment::{providers::Env, Figment};
+use sentry::integrations::tracing::{event_from_event, EventMapping, SentryLayer};
+use sentry::protocol::{Context as SentryContext, Event as SentryEvent, Request, Value};
+use sentry::{ClientInitGuard, ClientOptions};
+use serde::{Deserialize, Serialize};
+use tracing::{Event, Level};
+use tracing_opentelemetry::OtelData;
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::Registry;
+
+use super::redact::Redactor;
+
+// Flushes the queued events when it is dropped
+static CLIENT_GUARD: Mutex<Option<ClientInitGuard>> = Mutex::new(None);
+
+/// Reports error events and panics to a Sentry compatible endpoint.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ErrorReportingConfig {
+    pub dsn: Option<String>,
+    pub environment: Option<String>,
+    pub release: Option<String>,
+    /// Share of the error events that are sent, between 0.0 and 1.0
+    pub sample_rate: Option<f32>,
+}
+
+pub fn get_error_reporting_config(figment: &Figment) -> ErrorReportingConfig {
+    figment
+        .clone()
+        .select("error_reporting")
+        .merge(Env::prefixed("VULPO_ERROR_REPORTING_").global())
+        .extract::<ErrorReportingConfig>()
+        .expect("Invalid error reporting config")
+}
+
+/// Initializes the client and returns the layer that turns `error!` events into reports,
+/// `None` if no DSN is configured.
+pub fn layer(
+    service_name: &'static str,
+    figment: &Figment,
+    redactor: Arc<Redactor>,
+) -> Option<SentryLayer<Registry>> {
+    let config = get_error_reporting_config(figment);
+    let dsn = config.dsn.as_deref()?;
+
+    let dsn = match dsn.parse() {
+        Ok(dsn) => dsn,
+        Err(err) => {
+            opentelemetry::global::handle_error(opentelemetry::global::Error::Other(format!(
+                "Invalid error reporting DSN, errors are not reported: {}",
+                err
+            )));
+            return None;
+        }
+    };
+
+    let guard = sentry::init(ClientOptions {
+        dsn: Some(dsn),
+        environment: config.environment.map(Cow::Owned),
+        release: config.release.map(Cow::Owned),
+        sample_rate: config.sample_rate.unwrap_or(1.0),
+        server_name: Some(Cow::Borrowed(service_name)),
+        ..Default::default()
+    });
+    *CLIENT_GUARD.lock().unwrap() = Some(guard);
+
+    // Spans are exported through OpenTelemetry, the layer only reports events
+    Some(
+        sentry::integrations::tracing::layer()
+            .span_filter(|_| false)
+            .event_mapper(move |event, ctx| match *event.metadata().level() {
+                Level::ERROR => EventMapping::Event(request_event(event, ctx, &redactor)),
+                _ => EventMapping::Ignore,
+            }),
+    )
+}
+
+pub fn shutdown() {
+    CLIENT_GUARD.lock().unwrap().take();
+}
+
+fn request_event(
+    event: &Event<'_>,
+    ctx: Context<'_, Registry>,
+    redactor: &Redactor,
+) -> SentryEvent<'static> {
+    let attributes = request_attributes(event, &ctx);
+    let mut report = event_from_event(event, ctx);
+
+    report.message = report
+        .message
+        .map(|message| redactor.redact_value(&message).into_owned());
+    for context in report.contexts.values_mut() {
+        if let SentryContext::Other(fields) = context {
+            for (name, value) in fields.iter_mut() {
+                if let Value::String(string) = value {
+                    *string = redactor.redact(name, string).into_owned();
+                }
+            }
+        }
+    }
+
+    let attribute = |key: &str| {
+        attributes
+            .iter()
+            .find(|(name, _)| name == key)
+            .map(|(_, value)| value.clone())
+    };
+
+    for (tag, key) in [
+        ("request_id", "http.request_id"),
+        ("project_id", "vulpo.project_id"),
+        ("route", "http.route"),
+    ] {
+        if let Some(value) = attribute(key).filter(|value| !value.is_empty()) {
+            report.tags.insert(tag.to_string(), value);
+        }
+    }
+
+    if let (Some(method), Some(route)) = (attribute("http.method"), attribute("http.route")) {
+        report.transaction = Some(format!("{} {}", method, route));
+    }
+
+    if attribute("http.method").is_some() {
+        let mut request = Request {
+            method: attribute("http.method"),
+            ..Default::default()
+        };
+        if let Some(user_agent) = attribute("http.user_agent").filter(|value| !value.is_empty()) {
+            request.headers.insert("User-Agent".to_string(), user_agent);
+        }
+        report.request = Some(request);
+    }
+
+    report
+}
+
+/// Collects the attributes of the spans the event belongs to, inner spans take precedence.
+fn request_attributes(event: &Event<'_>, ctx: &Context<'_, Registry>) -> Vec<(String, String)> {
+    let mut attributes = Vec::new();
+    let span = match ctx.event_span(event) {
+        Some(span) => span,
+        None => return attributes,
+    };
+
+    for span in span.scope() {
+        let extensions = span.extensions();
+        let recorded = extensions
+            .get::<OtelData>()
+            .and_then(|data| data.builder.attributes.as_ref());
+
+        for key_value in recorded.into_iter().flatten() {
+            attributes.push((
+                key_value.key.as_str().to_string(),
+                key_value.value.to_string(),
+            ));
+        }
+    }
+
+    attributes
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    use figment::providers::Serialized;
+    use opentelemetry::trace::TracerProvider;
+    use serde_json::json;
+    use tracing::{error, info_span};
+    use tracing_subscriber::layer::SubscriberExt;
+    use tracing_subscriber::Layer;
+
+    use super::*;
+    use crate::otel::redact::RedactConfig;
+
+    // Answers every request with 200 and hands the request bodies to the test
+    fn mock_endpoint() -> (String, mpsc::Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (sender, receiver) = mpsc::channel();
+
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut content_length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    let line = line.trim_end().to_lowercase();
+                    if line.is_empty() {
+                        break;
+                    }
+                    if let Some(length) = line.strip_prefix("content-length:") {
+                        content_length = length.trim().parse().unwrap();
+                    }
+                }
+
+                let mut body = vec![0; content_length];
+                reader.read_exact(&mut body).unwrap();
+                stream
+                    .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 2\r\n\r\n{}")
+                    .unwrap();
+                let _ = sender.send(String::from_utf8_lossy(&body).into_owned());
+            }
+        });
+
+        (format!("http://public@127.0.0.1:{}/1", port), receiver)
+    }
+
+    #[test]
+    fn reports_errors_with_request_context() {
+        let (dsn, requests) = mock_endpoint();
+        let figment = Figment::new()
+            .merge(Serialized::defaults(json!({ "dsn": dsn })).profile("error_reporting"));
+        let redactor = Arc::new(Redactor::new(&RedactConfig::default()));
+        let tracer = opentelemetry::sdk::trace::TracerProvider::builder()
+            .build()
+            .tracer("test");
+
+        let layers: Vec<Box<dyn tracing_subscriber::Layer<Registry> + Send + Sync>> = vec![
+            tracing_opentelemetry::layer().with_tracer(tracer).boxed(),
+            layer("test", &figment, redactor).unwrap().boxed(),
+        ];
+        let subscriber = Registry::default().with(layers);
+
+        tracing::subscriber::with_default(subscriber, || {
+            let span = info_span!(
+                "HTTP Request",
+                http.method = "GET",
+                http.route = "/items/<id>",
+                http.request_id = "f5b2c4d0-request",
+                vulpo.project_id = "67e55044-10b1-426f-9247-bb680e5fe0c8",
+            );
+            span.in_scope(|| error!("Failed to load item"));
+        });
+        shutdown();
+
+        let envelope = requests.recv_timeout(Duration::from_secs(10)).unwrap();
+        let event = envelope
+            .lines()
+            .filter_map(|line| serde_json::from_str::<serde_json::Value>(line).ok())
+            .find(|item| item.get("tags").is_some())
+            .expect("envelope contains an event");
+
+        assert_eq!(event["tags"]["request_id"], "f5b2c4d0-request");
+        assert_eq!(
+            event["tags"]["project_id"],
+            "67e55044-10b1-426f-9247-bb680e5fe0c8"
+        );
+        assert_eq!(event["tags"]["route"], "/items/<id>");
+        assert_eq!(event["transaction"], "GET /items/<id>");
+    }
+}
@@ -0,0 +1,289 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use tracing_appender::non_blocking::{NonBlocking, WorkerGuard};
+use tracing_appender::rolling::{RollingFileAppender, Rotation as TimeRotation};
+use tracing_subscriber::field::MakeVisitor;
+
+use super::LogFormat;
+
+const DEFAULT_MAX_SIZE: u64 = 10 * 1024 * 1024;
+const DEFAULT_MAX_FILES: usize = 7;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LogFileConfig {
+    pub path: PathBuf,
+    pub rotation: Option<Rotation>,
+    /// Maximum file size in bytes before the file is rotated, only used with `Rotation::Size`
+    pub max_size: Option<u64>,
+    /// Number of rotated files that are kept
+    pub max_files: Option<usize>,
+    pub format: Option<LogFormat>,
+    pub level: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum Rotation {
+    #[serde(alias = "minutely", alias = "Minutely", alias = "MINUTELY")]
+    Minutely,
+
+    #[serde(alias = "hourly", alias = "Hourly", alias = "HOURLY")]
+    Hourly,
+
+    #[serde(alias = "daily", alias = "Daily", alias = "DAILY")]
+    Daily,
+
+    #[serde(alias = "size", alias = "Size", alias = "SIZE")]
+    Size,
+
+    #[serde(alias = "never", alias = "Never", alias = "NEVER")]
+    Never,
+}
+
+pub fn writer(config: &LogFileConfig) -> (NonBlocking, WorkerGuard) {
+    let max_files = config.max_files.unwrap_or(DEFAULT_MAX_FILES);
+    let rotation = config.rotation.as_ref().unwrap_or(&Rotation::Daily);
+
+    let time_rotation = match rotation {
+        Rotation::Minutely => TimeRotation::MINUTELY,
+        Rotation::Hourly => TimeRotation::HOURLY,
+        Rotation::Daily => TimeRotation::DAILY,
+        Rotation::Never => TimeRotation::NEVER,
+        Rotation::Size => {
+            let max_size = config.max_size.unwrap_or(DEFAULT_MAX_SIZE);
+            let file = SizeRollingFile::new(&config.path, max_size, max_files)
+                .expect("Failed to open log file");
+            return tracing_appender::non_blocking(file);
+        }
+    };
+
+    let directory = config
+        .path
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or_else(|| Path::new("."));
+
+    let file_name = config
+        .path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .expect("log file path must contain a file name");
+
+    fs::create_dir_all(directory).unwrap_or_else(|err| {
+        panic!(
+            "Failed to create the log directory {}: {}",
+            directory.display(),
+            err
+        )
+    });
+
+    let appender = RollingFileAppender::builder()
+        .rotation(time_rotation)
+        .filename_prefix(file_name)
+        .max_log_files(max_files + 1)
+        .build(directory)
+        .expect("Failed to open log file");
+
+    tracing_appender::non_blocking(appender)
+}
+
+/// Formats span fields for the log file. Formatted span fields are cached per formatter type,
+/// a type of its own keeps the colored fields of stdout out of the file.
+pub struct FileFields<M>(pub M);
+
+impl<T, M: MakeVisitor<T>> MakeVisitor<T> for FileFields<M> {
+    type Visitor = M::Visitor;
+
+    fn make_visitor(&self, target: T) -> Self::Visitor {
+        self.0.make_visitor(target)
+    }
+}
+
+/// Rotates `path` to `path.1`, `path.2`, ... once it grows beyond `max_size`
+/// bytes and deletes everything beyond `path.{max_files}`.
+struct SizeRollingFile {
+    path: PathBuf,
+    max_size: u64,
+    max_files: usize,
+    file: File,
+    size: u64,
+}
+
+impl SizeRollingFile {
+    fn new(path: &Path, max_size: u64, max_files: usize) -> io::Result<Self> {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let size = file.metadata()?.len();
+
+        Ok(SizeRollingFile {
+            path: path.to_path_buf(),
+            max_size,
+            max_files,
+            file,
+            size,
+        })
+    }
+
+    fn rotated_path(&self, index: usize) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{}", index));
+        PathBuf::from(path)
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+
+        if self.max_files == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            let _ = fs::remove_file(self.rotated_path(self.max_files));
+            for index in (1..self.max_files).rev() {
+                let from = self.rotated_path(index);
+                if from.exists() {
+                    fs::rename(from, self.rotated_path(index + 1))?;
+                }
+            }
+            fs::rename(&self.path, self.rotated_path(1))?;
+        }
+
+        self.file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+impl Write for SizeRollingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.size > 0 && self.size + buf.len() as u64 > self.max_size {
+            self.rotate()?;
+        }
+
+        let written = self.file.write(buf)?;
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("werkbank-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn read(path: PathBuf) -> String {
+        fs::read_to_string(path).unwrap()
+    }
+
+    #[test]
+    fn size_rotation_moves_full_files() {
+        let dir = temp_dir("size-rotation");
+        let path = dir.join("logs").join("app.log");
+        let mut file = SizeRollingFile::new(&path, 10, 3).unwrap();
+
+        file.write_all(b"first\n").unwrap();
+        file.write_all(b"second\n").unwrap();
+        file.write_all(b"third\n").unwrap();
+        file.flush().unwrap();
+
+        assert_eq!(read(path.clone()), "third\n");
+        assert_eq!(read(file.rotated_path(1)), "second\n");
+        assert_eq!(read(file.rotated_path(2)), "first\n");
+        assert!(!file.rotated_path(3).exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn size_rotation_keeps_max_files() {
+        let dir = temp_dir("size-pruning");
+        let path = dir.join("app.log");
+        let mut file = SizeRollingFile::new(&path, 4, 2).unwrap();
+
+        for line in ["one\n", "two\n", "six\n", "ten\n"] {
+            file.write_all(line.as_bytes()).unwrap();
+        }
+        file.flush().unwrap();
+
+        assert_eq!(read(path.clone()), "ten\n");
+        assert_eq!(read(file.rotated_path(1)), "six\n");
+        assert_eq!(read(file.rotated_path(2)), "two\n");
+        assert!(!file.rotated_path(3).exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn size_rotation_appends_to_existing_file() {
+        let dir = temp_dir("size-append");
+        let path = dir.join("app.log");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(&path, "old\n").unwrap();
+
+        let mut file = SizeRollingFile::new(&path, 6, 1).unwrap();
+        file.write_all(b"new\n").unwrap();
+        file.flush().unwrap();
+
+        assert_eq!(read(path.clone()), "new\n");
+        assert_eq!(read(file.rotated_path(1)), "old\n");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn size_rotation_without_kept_files() {
+        let dir = temp_dir("size-none");
+        let path = dir.join("app.log");
+        let mut file = SizeRollingFile::new(&path, 4, 0).unwrap();
+
+        file.write_all(b"one\n").unwrap();
+        file.write_all(b"two\n").unwrap();
+        file.flush().unwrap();
+
+        assert_eq!(read(path.clone()), "two\n");
+        assert!(!file.rotated_path(1).exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn time_rotation_creates_the_directory() {
+        let dir = temp_dir("time-rotation");
+        let config = LogFileConfig {
+            path: dir.join("logs").join("app.log"),
+            rotation: Some(Rotation::Daily),
+            max_size: None,
+            max_files: None,
+            format: None,
+            level: None,
+        };
+
+        let (mut writer, guard) = writer(&config);
+        writer.write_all(b"line\n").unwrap();
+        drop(guard);
+
+        let files: Vec<_> = fs::read_dir(dir.join("logs"))
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        assert_eq!(files.len(), 1);
+        assert!(files[0].starts_with("app.log"), "{:?}", files);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,85 @@
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use figment::providers::Env;
+use serde::{Deserialize, Serialize};
+use tracing_subscriber::filter::{Directive, ParseError};
+use tracing_subscriber::{reload, EnvFilter, Registry};
+
+pub const DEFAULT_LOG_LEVEL: &str = "info,_=off";
+
+// Handles to the filters of the stdout and otel layers, the log file keeps its own level
+static RELOAD_HANDLES: Mutex<Vec<reload::Handle<EnvFilter, Registry>>> = Mutex::new(Vec::new());
+
+/// How the configured log levels (`log_level` and `[otel.log_levels]`) relate to `RUST_LOG`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum LogLevelPrecedence {
+    /// `RUST_LOG` replaces the configured levels when it is set. This is the default.
+    #[serde(alias = "env", alias = "Env", alias = "ENV")]
+    Env,
+
+    /// The configured levels are used and `RUST_LOG` is ignored.
+    #[serde(alias = "config", alias = "Config", alias = "CONFIG")]
+    Config,
+
+    /// Both are combined, directives from `RUST_LOG` override the configured
+    /// level for the same target.
+    #[serde(alias = "merge", alias = "Merge", alias = "MERGE")]
+    Merge,
+}
+
+/// Compiles the base level and the per target overrides into a single list of directives.
+pub fn config_directives(
+    base_level: Option<&str>,
+    log_levels: &BTreeMap<String, String>,
+) -> String {
+    let mut directives = vec![base_level.unwrap_or(DEFAULT_LOG_LEVEL).to_string()];
+    directives.extend(
+        log_levels
+            .iter()
+            .map(|(target, level)| format!("{}={}", target, level)),
+    );
+    directives.join(",")
+}
+
+pub fn env_filter(directives: &str, precedence: &LogLevelPrecedence) -> EnvFilter {
+    let rust_log = Env::var(EnvFilter::DEFAULT_ENV).filter(|value| !value.trim().is_empty());
+
+    match (precedence, rust_log) {
+        (LogLevelPrecedence::Env, Some(rust_log)) => EnvFilter::new(rust_log),
+        (LogLevelPrecedence::Merge, Some(rust_log)) => rust_log
+            .split(',')
+            .filter_map(|directive| directive.trim().parse::<Directive>().ok())
+            .fold(EnvFilter::new(directives), EnvFilter::add_directive),
+        _ => EnvFilter::new(directives),
+    }
+}
+
+pub fn reloadable(filter: EnvFilter) -> reload::Layer<EnvFilter, Registry> {
+    let (layer, handle) = reload::Layer::new(filter);
+    RELOAD_HANDLES.lock().unwrap().push(handle);
+    layer
+}
+
+/// The directives that are currently active, `None` if `otel::init` wasn't called.
+pub fn current_directives() -> Option<String> {
+    RELOAD_HANDLES
+        .lock()
+        .unwrap()
+        .first()
+        .and_then(|handle| handle.with_current(ToString::to_string).ok())
+}
+
+/// Replaces the active directives at runtime, `RUST_LOG` and the configured levels are ignored.
+pub fn set_directives(directives: &str) -> Result<(), ParseError> {
+    EnvFilter::try_new(directives)?;
+
+    for handle in RELOAD_HANDLES.lock().unwrap().iter() {
+        if let Err(err) = handle.reload(EnvFilter::new(directives)) {
+            tracing::warn!("Failed to reload log filter: {}", err);
+        }
+    }
+
+    tracing::info!("Log filter changed to {}", directives);
+    Ok(())
+}
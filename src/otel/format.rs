@@ -0,0 +1,422 @@
+use std::fmt;
+use std::sync::Arc;
+
+use opentelemetry::sdk::trace::SamplingDecision;
+use opentelemetry::trace::{SpanId, TraceContextExt, TraceId};
+use serde_json::{Map, Value};
+use tracing::{Event, Level, Subscriber};
+use tracing_opentelemetry::OtelData;
+use tracing_subscriber::fmt::format::{Format, Writer};
+use tracing_subscriber::fmt::time::{FormatTime, SystemTime};
+use tracing_subscriber::fmt::{FmtContext, FormatEvent, FormatFields, FormattedFields};
+use tracing_subscriber::registry::{LookupSpan, SpanRef};
+
+use super::redact::{JsonFieldVisitor, Redactor};
+
+pub(super) struct TraceIds {
+    pub(super) trace_id: TraceId,
+    pub(super) span_id: SpanId,
+    pub(super) sampled: bool,
+}
+
+pub(super) fn trace_ids<S>(span: &SpanRef<'_, S>) -> Option<TraceIds>
+where
+    S: for<'a> LookupSpan<'a>,
+{
+    let extensions = span.extensions();
+    let data = extensions.get::<OtelData>()?;
+    let span_id = data.builder.span_id?;
+
+    // Only root spans get a trace id assigned, all others inherit it from their parent
+    let trace_id = if data.parent_cx.has_active_span() {
+        data.parent_cx.span().span_context().trace_id()
+    } else {
+        data.builder.trace_id?
+    };
+
+    // The sampling decision is made once the span's context is first requested, until then
+    // the span follows its parent
+    let sampled = match &data.builder.sampling_result {
+        Some(result) => result.decision == SamplingDecision::RecordAndSample,
+        None => data.parent_cx.span().span_context().is_sampled(),
+    };
+
+    Some(TraceIds {
+        trace_id,
+        span_id,
+        sampled,
+    })
+}
+
+fn current_trace_ids<S, N>(ctx: &FmtContext<'_, S, N>) -> Option<TraceIds>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    ctx.parent_span().and_then(|span| trace_ids(&span))
+}
+
+fn timestamp() -> String {
+    let mut timestamp = String::new();
+    let _ = SystemTime.format_time(&mut Writer::new(&mut timestamp));
+    timestamp
+}
+
+/// Wraps one of the human readable `tracing_subscriber` formats and appends the
+/// trace and span id to every line.
+pub struct TextFormat<F> {
+    ansi: Format<F>,
+    plain: Format<F>,
+}
+
+impl<F: Clone> TextFormat<F> {
+    pub fn new(format: Format<F>) -> Self {
+        TextFormat {
+            ansi: format.clone().with_ansi(true),
+            plain: format.with_ansi(false),
+        }
+    }
+}
+
+impl<S, N, F> FormatEvent<S, N> for TextFormat<F>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+    Format<F>: FormatEvent<S, N>,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        let format = if writer.has_ansi_escapes() {
+            &self.ansi
+        } else {
+            &self.plain
+        };
+
+        let ids = match current_trace_ids(ctx) {
+            None => return format.format_event(ctx, writer, event),
+            Some(ids) => ids,
+        };
+
+        let mut line = String::new();
+        format.format_event(ctx, Writer::new(&mut line), event)?;
+
+        writeln!(
+            writer,
+            "{} trace_id={:032x} span_id={:016x}",
+            line.trim_end_matches('\n'),
+            ids.trace_id,
+            ids.span_id
+        )
+    }
+}
+
+fn event_fields(event: &Event<'_>, redactor: &Arc<Redactor>) -> Map<String, Value> {
+    let mut visitor = JsonFieldVisitor::new(redactor.clone());
+    event.record(&mut visitor);
+    visitor.into_fields()
+}
+
+/// Fields of all spans in the event's scope, from the root to the leaf span.
+/// Relies on the span fields being formatted as JSON, e.g. with `RedactedJsonFields`.
+fn span_fields<S, N>(ctx: &FmtContext<'_, S, N>) -> Vec<(&'static str, Map<String, Value>)>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    let scope = match ctx.event_scope() {
+        None => return Vec::new(),
+        Some(scope) => scope,
+    };
+
+    scope
+        .from_root()
+        .map(|span| {
+            let fields = span
+                .extensions()
+                .get::<FormattedFields<N>>()
+                .and_then(|fields| serde_json::from_str::<Map<String, Value>>(fields).ok())
+                .unwrap_or_default();
+            (span.name(), fields)
+        })
+        .collect()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum JsonLayout {
+    Default,
+    Ecs,
+    Gcp,
+}
+
+/// JSON output in either our default layout, the Elastic Common Schema or the
+/// layout expected by Google Cloud Logging.
+pub struct JsonFormat {
+    layout: JsonLayout,
+    gcp_project_id: Option<String>,
+    redactor: Arc<Redactor>,
+}
+
+impl JsonFormat {
+    pub fn new(layout: JsonLayout, redactor: Arc<Redactor>) -> Self {
+        JsonFormat {
+            layout,
+            gcp_project_id: None,
+            redactor,
+        }
+    }
+
+    pub fn with_gcp_project_id(self, gcp_project_id: Option<String>) -> Self {
+        JsonFormat {
+            gcp_project_id,
+            ..self
+        }
+    }
+
+    fn default_layout<S, N>(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        event: &Event<'_>,
+        ids: Option<TraceIds>,
+    ) -> Map<String, Value>
+    where
+        S: Subscriber + for<'a> LookupSpan<'a>,
+        N: for<'a> FormatFields<'a> + 'static,
+    {
+        let meta = event.metadata();
+        let mut output = Map::new();
+        output.insert("timestamp".into(), timestamp().into());
+        output.insert("level".into(), meta.level().as_str().into());
+        output.extend(event_fields(event, &self.redactor));
+        output.insert("target".into(), meta.target().into());
+
+        let spans = span_fields(ctx)
+            .into_iter()
+            .map(|(name, mut fields)| {
+                fields.insert("name".into(), name.into());
+                Value::Object(fields)
+            })
+            .collect::<Vec<Value>>();
+
+        if let Some(span) = spans.last() {
+            output.insert("span".into(), span.clone());
+            output.insert("spans".into(), spans.into());
+        }
+
+        if let Some(ids) = ids {
+            output.insert("trace_id".into(), format!("{:032x}", ids.trace_id).into());
+            output.insert("span_id".into(), format!("{:016x}", ids.span_id).into());
+        }
+
+        output
+    }
+
+    fn ecs_layout<S, N>(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        event: &Event<'_>,
+        ids: Option<TraceIds>,
+    ) -> Map<String, Value>
+    where
+        S: Subscriber + for<'a> LookupSpan<'a>,
+        N: for<'a> FormatFields<'a> + 'static,
+    {
+        let meta = event.metadata();
+        let mut output = Map::new();
+
+        for (_, fields) in span_fields(ctx) {
+            output.extend(fields);
+        }
+
+        output.extend(event_fields(event, &self.redactor));
+        output.insert("@timestamp".into(), timestamp().into());
+        output.insert(
+            "log.level".into(),
+            meta.level().as_str().to_lowercase().into(),
+        );
+        output.insert("log.logger".into(), meta.target().into());
+        output.insert("ecs.version".into(), "1.6.0".into());
+
+        if let Some(file) = meta.file() {
+            output.insert("log.origin.file.name".into(), file.into());
+        }
+
+        if let Some(line) = meta.line() {
+            output.insert("log.origin.file.line".into(), line.into());
+        }
+
+        if let Some(ids) = ids {
+            output.insert("trace.id".into(), format!("{:032x}", ids.trace_id).into());
+            output.insert("span.id".into(), format!("{:016x}", ids.span_id).into());
+        }
+
+        output
+    }
+
+    fn gcp_layout<S, N>(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        event: &Event<'_>,
+        ids: Option<TraceIds>,
+    ) -> Map<String, Value>
+    where
+        S: Subscriber + for<'a> LookupSpan<'a>,
+        N: for<'a> FormatFields<'a> + 'static,
+    {
+        let meta = event.metadata();
+        let mut output = Map::new();
+
+        for (_, fields) in span_fields(ctx) {
+            output.extend(fields);
+        }
+
+        output.extend(event_fields(event, &self.redactor));
+        output.insert("timestamp".into(), timestamp().into());
+        output.insert("severity".into(), gcp_severity(meta.level()).into());
+
+        let mut source_location = Map::new();
+        source_location.insert("function".into(), meta.target().into());
+        if let Some(file) = meta.file() {
+            source_location.insert("file".into(), file.into());
+        }
+        if let Some(line) = meta.line() {
+            source_location.insert("line".into(), line.to_string().into());
+        }
+        output.insert(
+            "logging.googleapis.com/sourceLocation".into(),
+            source_location.into(),
+        );
+
+        if let Some(ids) = ids {
+            let trace_id = format!("{:032x}", ids.trace_id);
+            let trace = match &self.gcp_project_id {
+                Some(project_id) => format!("projects/{}/traces/{}", project_id, trace_id),
+                None => trace_id,
+            };
+
+            output.insert("logging.googleapis.com/trace".into(), trace.into());
+            output.insert(
+                "logging.googleapis.com/spanId".into(),
+                format!("{:016x}", ids.span_id).into(),
+            );
+        }
+
+        output
+    }
+}
+
+fn gcp_severity(level: &Level) -> &'static str {
+    match *level {
+        Level::ERROR => "ERROR",
+        Level::WARN => "WARNING",
+        Level::INFO => "INFO",
+        Level::DEBUG | Level::TRACE => "DEBUG",
+    }
+}
+
+impl<S, N> FormatEvent<S, N> for JsonFormat
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        let ids = current_trace_ids(ctx);
+        let output = match self.layout {
+            JsonLayout::Default => self.default_layout(ctx, event, ids),
+            JsonLayout::Ecs => self.ecs_layout(ctx, event, ids),
+            JsonLayout::Gcp => self.gcp_layout(ctx, event, ids),
+        };
+
+        let output = serde_json::to_string(&output).map_err(|_| fmt::Error)?;
+        writeln!(writer, "{}", output)
+    }
+}
+
+/// `key=value` pairs as understood by logfmt parsers such as Loki or Heroku. Like the JSON
+/// formats it needs the span fields formatted as JSON.
+pub struct LogfmtFormat {
+    redactor: Arc<Redactor>,
+}
+
+impl LogfmtFormat {
+    pub fn new(redactor: Arc<Redactor>) -> Self {
+        LogfmtFormat { redactor }
+    }
+}
+
+fn write_logfmt_value(writer: &mut Writer<'_>, value: &Value) -> fmt::Result {
+    let value = match value {
+        Value::String(value) => value.to_owned(),
+        value => value.to_string(),
+    };
+
+    let needs_quotes = value.is_empty()
+        || value
+            .chars()
+            .any(|c| c.is_whitespace() || c == '=' || c == '"' || c.is_control());
+
+    if needs_quotes {
+        write!(writer, "{:?}", value)
+    } else {
+        write!(writer, "{}", value)
+    }
+}
+
+impl<S, N> FormatEvent<S, N> for LogfmtFormat
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        let meta = event.metadata();
+        let mut fields = event_fields(event, &self.redactor);
+
+        write!(
+            writer,
+            "ts={} level={} target={}",
+            timestamp(),
+            meta.level().as_str().to_lowercase(),
+            meta.target()
+        )?;
+
+        if let Some(message) = fields.remove("message") {
+            write!(writer, " msg=")?;
+            write_logfmt_value(&mut writer, &message)?;
+        }
+
+        for (key, value) in fields.iter() {
+            write!(writer, " {}=", key)?;
+            write_logfmt_value(&mut writer, value)?;
+        }
+
+        for (_, fields) in span_fields(ctx) {
+            for (key, value) in fields.iter() {
+                write!(writer, " {}=", key)?;
+                write_logfmt_value(&mut writer, value)?;
+            }
+        }
+
+        if let Some(ids) = current_trace_ids(ctx) {
+            write!(
+                writer,
+                " trace_id={:032x} span_id={:016x}",
+                ids.trace_id, ids.span_id
+            )?;
+        }
+
+        writeln!(writer)
+    }
+}
@@ -0,0 +1,526 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use opentelemetry::global;
+use opentelemetry::trace::TraceFlags;
+use serde_json::Value;
+use tokio::sync::{mpsc, oneshot};
+use tonic::client::Grpc;
+use tonic::codec::ProstCodec;
+use tonic::codegen::http::uri::PathAndQuery;
+use tonic::transport::Channel;
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+use tracing_subscriber::registry::LookupSpan;
+
+use super::format::trace_ids;
+use super::redact::{JsonFieldVisitor, Redactor};
+
+const EXPORT_PATH: &str = "/opentelemetry.proto.collector.logs.v1.LogsService/Export";
+const EXPORT_INTERVAL: Duration = Duration::from_secs(5);
+const EXPORT_TIMEOUT: Duration = Duration::from_secs(10);
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+const MAX_QUEUE_SIZE: usize = 2048;
+const MAX_BATCH_SIZE: usize = 512;
+
+// Events of the gRPC stack are dropped, exporting them would produce new events on every export
+const IGNORED_TARGETS: &[&str] = &["h2", "hyper", "tonic", "tower"];
+
+static EXPORTER: Mutex<Option<mpsc::Sender<Message>>> = Mutex::new(None);
+
+// The OTLP logs protocol, opentelemetry-otlp 0.10 doesn't support logs and keeps its
+// generated messages private
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ExportLogsServiceRequest {
+    #[prost(message, repeated, tag = "1")]
+    pub resource_logs: Vec<ResourceLogs>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ExportLogsServiceResponse {}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ResourceLogs {
+    #[prost(message, optional, tag = "1")]
+    pub resource: Option<Resource>,
+    #[prost(message, repeated, tag = "2")]
+    pub scope_logs: Vec<ScopeLogs>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ScopeLogs {
+    #[prost(message, optional, tag = "1")]
+    pub scope: Option<InstrumentationScope>,
+    #[prost(message, repeated, tag = "2")]
+    pub log_records: Vec<LogRecord>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Resource {
+    #[prost(message, repeated, tag = "1")]
+    pub attributes: Vec<KeyValue>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct InstrumentationScope {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(string, tag = "2")]
+    pub version: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct KeyValue {
+    #[prost(string, tag = "1")]
+    pub key: String,
+    #[prost(message, optional, tag = "2")]
+    pub value: Option<AnyValue>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct KeyValueList {
+    #[prost(message, repeated, tag = "1")]
+    pub values: Vec<KeyValue>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ArrayValue {
+    #[prost(message, repeated, tag = "1")]
+    pub values: Vec<AnyValue>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct AnyValue {
+    #[prost(oneof = "any_value::Value", tags = "1, 2, 3, 4, 5, 6")]
+    pub value: Option<any_value::Value>,
+}
+
+pub mod any_value {
+    #[derive(Clone, PartialEq, prost::Oneof)]
+    pub enum Value {
+        #[prost(string, tag = "1")]
+        StringValue(String),
+        #[prost(bool, tag = "2")]
+        BoolValue(bool),
+        #[prost(int64, tag = "3")]
+        IntValue(i64),
+        #[prost(double, tag = "4")]
+        DoubleValue(f64),
+        #[prost(message, tag = "5")]
+        ArrayValue(super::ArrayValue),
+        #[prost(message, tag = "6")]
+        KvlistValue(super::KeyValueList),
+    }
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct LogRecord {
+    #[prost(fixed64, tag = "1")]
+    pub time_unix_nano: u64,
+    #[prost(fixed64, tag = "11")]
+    pub observed_time_unix_nano: u64,
+    #[prost(int32, tag = "2")]
+    pub severity_number: i32,
+    #[prost(string, tag = "3")]
+    pub severity_text: String,
+    #[prost(message, optional, tag = "5")]
+    pub body: Option<AnyValue>,
+    #[prost(message, repeated, tag = "6")]
+    pub attributes: Vec<KeyValue>,
+    #[prost(fixed32, tag = "8")]
+    pub flags: u32,
+    #[prost(bytes = "vec", tag = "9")]
+    pub trace_id: Vec<u8>,
+    #[prost(bytes = "vec", tag = "10")]
+    pub span_id: Vec<u8>,
+}
+
+enum Message {
+    Record(Box<LogRecord>),
+    Flush(oneshot::Sender<()>),
+}
+
+/// Sends `tracing` events as OTLP log records to the collector, batched by a
+/// background task. Must be created inside a tokio runtime.
+pub struct OtlpLogLayer {
+    sender: mpsc::Sender<Message>,
+    redactor: Arc<Redactor>,
+}
+
+impl OtlpLogLayer {
+    pub fn new(
+        endpoint: &str,
+        service_name: &'static str,
+        redactor: Arc<Redactor>,
+    ) -> OtlpLogLayer {
+        let (sender, receiver) = mpsc::channel(MAX_QUEUE_SIZE);
+        let resource = Resource {
+            attributes: vec![string_attribute("service.name", service_name)],
+        };
+
+        tokio::spawn(export_loop(endpoint.to_string(), resource, receiver));
+        *EXPORTER.lock().unwrap() = Some(sender.clone());
+
+        OtlpLogLayer { sender, redactor }
+    }
+}
+
+impl<S> Layer<S> for OtlpLogLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let metadata = event.metadata();
+        if IGNORED_TARGETS
+            .iter()
+            .any(|target| metadata.target().starts_with(target))
+        {
+            return;
+        }
+
+        let mut visitor = JsonFieldVisitor::new(self.redactor.clone());
+        event.record(&mut visitor);
+        let mut fields = visitor.into_fields();
+
+        let body = fields.remove("message").map(any_value);
+        let mut attributes = fields
+            .into_iter()
+            .map(|(key, value)| KeyValue {
+                key,
+                value: Some(any_value(value)),
+            })
+            .collect::<Vec<KeyValue>>();
+        attributes.push(string_attribute("log.target", metadata.target()));
+        if let Some(file) = metadata.file() {
+            attributes.push(string_attribute("code.filepath", file));
+        }
+        if let Some(line) = metadata.line() {
+            attributes.push(KeyValue {
+                key: "code.lineno".to_string(),
+                value: Some(AnyValue {
+                    value: Some(any_value::Value::IntValue(line.into())),
+                }),
+            });
+        }
+
+        let ids = ctx.event_span(event).and_then(|span| trace_ids(&span));
+        let now = unix_nanos();
+
+        let record = LogRecord {
+            time_unix_nano: now,
+            observed_time_unix_nano: now,
+            severity_number: severity_number(metadata.level()),
+            severity_text: metadata.level().to_string(),
+            body,
+            attributes,
+            flags: ids
+                .as_ref()
+                .filter(|ids| ids.sampled)
+                .map(|_| TraceFlags::SAMPLED.to_u8().into())
+                .unwrap_or_default(),
+            trace_id: ids
+                .as_ref()
+                .map(|ids| ids.trace_id.to_bytes().to_vec())
+                .unwrap_or_default(),
+            span_id: ids
+                .as_ref()
+                .map(|ids| ids.span_id.to_bytes().to_vec())
+                .unwrap_or_default(),
+        };
+
+        // Records are dropped when the collector can't keep up instead of blocking the caller
+        let _ = self.sender.try_send(Message::Record(Box::new(record)));
+    }
+}
+
+/// Exports the queued log records and waits until the collector has received them.
+pub async fn shutdown() {
+    let sender = match EXPORTER.lock().unwrap().take() {
+        Some(sender) => sender,
+        None => return,
+    };
+
+    // The export task runs on the same runtime, so the wait must not block the thread
+    let (done, wait) = oneshot::channel();
+    let _ = tokio::time::timeout(SHUTDOWN_TIMEOUT, async move {
+        if sender.send(Message::Flush(done)).await.is_ok() {
+            let _ = wait.await;
+        }
+    })
+    .await;
+}
+
+async fn export_loop(endpoint: String, resource: Resource, mut receiver: mpsc::Receiver<Message>) {
+    let channel = match Channel::from_shared(endpoint) {
+        Ok(endpoint) => endpoint.timeout(EXPORT_TIMEOUT).connect_lazy(),
+        Err(err) => {
+            global::handle_error(global::Error::Other(format!(
+                "Invalid OTLP logs endpoint: {}",
+                err
+            )));
+            return;
+        }
+    };
+
+    let mut client = Grpc::new(channel);
+    let mut batch = Vec::new();
+    let mut interval = tokio::time::interval(EXPORT_INTERVAL);
+
+    loop {
+        tokio::select! {
+            message = receiver.recv() => match message {
+                Some(Message::Record(record)) => {
+                    batch.push(*record);
+                    if batch.len() >= MAX_BATCH_SIZE {
+                        export(&mut client, &resource, &mut batch).await;
+                    }
+                }
+                Some(Message::Flush(done)) => {
+                    export(&mut client, &resource, &mut batch).await;
+                    let _ = done.send(());
+                }
+                None => {
+                    export(&mut client, &resource, &mut batch).await;
+                    return;
+                }
+            },
+            _ = interval.tick() => export(&mut client, &resource, &mut batch).await,
+        }
+    }
+}
+
+async fn export(client: &mut Grpc<Channel>, resource: &Resource, batch: &mut Vec<LogRecord>) {
+    if batch.is_empty() {
+        return;
+    }
+
+    let request = export_request(resource, std::mem::take(batch));
+
+    let result = match client.ready().await {
+        Ok(()) => client
+            .unary::<_, ExportLogsServiceResponse, _>(
+                tonic::Request::new(request),
+                PathAndQuery::from_static(EXPORT_PATH),
+                ProstCodec::default(),
+            )
+            .await
+            .map(|_| ())
+            .map_err(|status| status.to_string()),
+        Err(err) => Err(err.to_string()),
+    };
+
+    if let Err(err) = result {
+        global::handle_error(global::Error::Other(format!(
+            "Failed to export logs: {}",
+            err
+        )));
+    }
+}
+
+fn export_request(resource: &Resource, log_records: Vec<LogRecord>) -> ExportLogsServiceRequest {
+    ExportLogsServiceRequest {
+        resource_logs: vec![ResourceLogs {
+            resource: Some(resource.clone()),
+            scope_logs: vec![ScopeLogs {
+                scope: Some(InstrumentationScope {
+                    name: env!("CARGO_PKG_NAME").to_string(),
+                    version: env!("CARGO_PKG_VERSION").to_string(),
+                }),
+                log_records,
+            }],
+        }],
+    }
+}
+
+fn severity_number(level: &Level) -> i32 {
+    match *level {
+        Level::TRACE => 1,
+        Level::DEBUG => 5,
+        Level::INFO => 9,
+        Level::WARN => 13,
+        Level::ERROR => 17,
+    }
+}
+
+fn unix_nanos() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_nanos() as u64)
+        .unwrap_or_default()
+}
+
+fn string_attribute(key: &str, value: &str) -> KeyValue {
+    KeyValue {
+        key: key.to_string(),
+        value: Some(AnyValue {
+            value: Some(any_value::Value::StringValue(value.to_string())),
+        }),
+    }
+}
+
+fn any_value(value: Value) -> AnyValue {
+    let value = match value {
+        Value::Null => None,
+        Value::Bool(value) => Some(any_value::Value::BoolValue(value)),
+        Value::Number(number) => match number.as_i64() {
+            Some(value) => Some(any_value::Value::IntValue(value)),
+            None => number.as_f64().map(any_value::Value::DoubleValue),
+        },
+        Value::String(value) => Some(any_value::Value::StringValue(value)),
+        Value::Array(values) => Some(any_value::Value::ArrayValue(ArrayValue {
+            values: values.into_iter().map(any_value).collect(),
+        })),
+        Value::Object(values) => Some(any_value::Value::KvlistValue(KeyValueList {
+            values: values
+                .into_iter()
+                .map(|(key, value)| KeyValue {
+                    key,
+                    value: Some(any_value(value)),
+                })
+                .collect(),
+        })),
+    };
+
+    AnyValue { value }
+}
+
+#[cfg(test)]
+mod tests {
+    use opentelemetry::trace::{TraceContextExt, TracerProvider};
+    use opentelemetry_proto::tonic::collector::logs::v1 as collector;
+    use opentelemetry_proto::tonic::common::v1 as common;
+    use opentelemetry_proto::tonic::logs::v1::SeverityNumber;
+    use prost_reference::Message as _;
+    use serde_json::json;
+    use tracing::info;
+    use tracing_opentelemetry::OpenTelemetrySpanExt;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    use super::super::redact::RedactConfig;
+    use super::*;
+
+    fn attribute(attributes: &[common::KeyValue], key: &str) -> Option<common::any_value::Value> {
+        attributes
+            .iter()
+            .find(|attribute| attribute.key == key)
+            .and_then(|attribute| attribute.value.clone()?.value)
+    }
+
+    fn string(value: &str) -> Option<common::any_value::Value> {
+        Some(common::any_value::Value::StringValue(value.to_string()))
+    }
+
+    // The messages are defined by hand, so they are checked against the generated OTLP types
+    #[test]
+    fn records_decode_as_otlp_logs() {
+        let (sender, mut receiver) = mpsc::channel(16);
+        let layer = OtlpLogLayer {
+            sender,
+            redactor: Arc::new(Redactor::new(&RedactConfig::default())),
+        };
+        // The tracer only holds a weak reference, the provider has to outlive the test
+        let provider = opentelemetry::sdk::trace::TracerProvider::builder().build();
+        let subscriber = tracing_subscriber::registry()
+            .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("test")))
+            .with(layer);
+
+        let context = tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!("request");
+            // Requesting the context makes the sampling decision, as for propagated requests
+            let context = span.context().span().span_context().clone();
+            let _entered = span.enter();
+            info!(
+                user.id = 42,
+                ratio = 0.5,
+                admin = true,
+                password = "hunter2",
+                "Loaded {}",
+                "profile"
+            );
+            context
+        });
+
+        let record = match receiver.try_recv() {
+            Ok(Message::Record(record)) => *record,
+            _ => panic!("No log record was queued"),
+        };
+        let nested = LogRecord {
+            body: Some(any_value(json!({ "ids": [1, "two"], "empty": null }))),
+            ..LogRecord::default()
+        };
+        let resource = Resource {
+            attributes: vec![string_attribute("service.name", "werkbank-test")],
+        };
+        let bytes = prost::Message::encode_to_vec(&export_request(&resource, vec![record, nested]));
+
+        let request = collector::ExportLogsServiceRequest::decode(bytes.as_slice()).unwrap();
+        let resource_logs = &request.resource_logs[0];
+        let resource = resource_logs.resource.as_ref().unwrap();
+        assert_eq!(
+            attribute(&resource.attributes, "service.name"),
+            string("werkbank-test")
+        );
+
+        let scope_logs = &resource_logs.scope_logs[0];
+        let scope = scope_logs.scope.as_ref().unwrap();
+        assert_eq!(scope.name, env!("CARGO_PKG_NAME"));
+        assert_eq!(scope.version, env!("CARGO_PKG_VERSION"));
+
+        let record = &scope_logs.log_records[0];
+        assert!(record.time_unix_nano > 0);
+        assert_eq!(record.observed_time_unix_nano, record.time_unix_nano);
+        assert_eq!(record.severity_number, SeverityNumber::Info as i32);
+        assert_eq!(record.severity_text, "INFO");
+        assert_eq!(
+            record.body.clone().and_then(|body| body.value),
+            string("Loaded profile")
+        );
+        assert_eq!(
+            attribute(&record.attributes, "user.id"),
+            Some(common::any_value::Value::IntValue(42))
+        );
+        assert_eq!(
+            attribute(&record.attributes, "ratio"),
+            Some(common::any_value::Value::DoubleValue(0.5))
+        );
+        assert_eq!(
+            attribute(&record.attributes, "admin"),
+            Some(common::any_value::Value::BoolValue(true))
+        );
+        assert_eq!(
+            attribute(&record.attributes, "password"),
+            string("[REDACTED]")
+        );
+        assert_eq!(
+            attribute(&record.attributes, "log.target"),
+            string(module_path!())
+        );
+        assert!(matches!(
+            attribute(&record.attributes, "code.lineno"),
+            Some(common::any_value::Value::IntValue(_))
+        ));
+        assert_eq!(record.flags, u32::from(TraceFlags::SAMPLED.to_u8()));
+        assert_eq!(record.trace_id, context.trace_id().to_bytes().to_vec());
+        assert_eq!(record.span_id, context.span_id().to_bytes().to_vec());
+
+        let body = scope_logs.log_records[1].body.as_ref().unwrap();
+        let values = match &body.value {
+            Some(common::any_value::Value::KvlistValue(list)) => &list.values,
+            other => panic!("Expected a key value list, got {:?}", other),
+        };
+        assert_eq!(attribute(values, "empty"), None);
+        assert_eq!(
+            attribute(values, "ids"),
+            Some(common::any_value::Value::ArrayValue(common::ArrayValue {
+                values: vec![
+                    common::AnyValue {
+                        value: Some(common::any_value::Value::IntValue(1)),
+                    },
+                    common::AnyValue {
+                        value: Some(common::any_value::Value::StringValue("two".to_string())),
+                    },
+                ],
+            }))
+        );
+    }
+}
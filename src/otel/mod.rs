@@ -0,0 +1,398 @@
+use figment::{providers::Env, Figment};
+use format::{JsonFormat, JsonLayout, LogfmtFormat, TextFormat};
+use opentelemetry::runtime::Tokio;
+use opentelemetry::sdk::metrics::controllers::PushController;
+use opentelemetry::trace::TracerProvider;
+use opentelemetry_otlp::{SpanExporterBuilder, WithExportConfig};
+use redact::{RedactConfig, RedactedFields, RedactedJsonFields, RedactingExporter, Redactor};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_subscriber::fmt::format::DefaultFields;
+use tracing_subscriber::fmt::FormatFields;
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::layer::{Layer, SubscriberExt};
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::Registry;
+
+#[cfg(feature = "error-reporting")]
+pub mod error_reporting;
+pub mod file;
+pub mod filter;
+pub mod format;
+pub mod logs;
+pub mod panic;
+pub mod propagation;
+pub mod redact;
+
+use file::FileFields;
+pub use file::LogFileConfig;
+pub use filter::LogLevelPrecedence;
+pub use propagation::Propagator;
+
+// Keeps the non-blocking log writers alive until `shutdown` is called
+static LOG_GUARDS: Mutex<Vec<WorkerGuard>> = Mutex::new(Vec::new());
+// Pushes the metrics to the collector until it is dropped
+static METRICS_CONTROLLER: Mutex<Option<PushController>> = Mutex::new(None);
+
+#[derive(Serialize, Deserialize)]
+pub struct OtelConfig {
+    pub address: Option<String>,
+    pub sample_ratio: Option<f64>,
+    pub log_level: Option<String>,
+    pub log_levels: Option<BTreeMap<String, String>>,
+    pub log_level_precedence: Option<LogLevelPrecedence>,
+    pub log_format: Option<LogFormat>,
+    pub propagators: Option<Vec<Propagator>>,
+    pub gcp_project_id: Option<String>,
+    pub log_stdout: Option<bool>,
+    pub log_file: Option<LogFileConfig>,
+    pub redact: Option<RedactConfig>,
+    pub signals: Option<SignalsConfig>,
+}
+
+/// The signals that are exported to `address`, all of them are enabled by default.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct SignalsConfig {
+    pub traces: Option<bool>,
+    pub metrics: Option<bool>,
+    pub logs: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum LogFormat {
+    #[serde(alias = "json", alias = "Json", alias = "JSON")]
+    Json,
+
+    #[serde(alias = "default", alias = "Default", alias = "DEFAULT")]
+    Default,
+
+    #[serde(alias = "pretty", alias = "Pretty", alias = "PRETTY")]
+    Pretty,
+
+    #[serde(alias = "compact", alias = "Compact", alias = "COMPACT")]
+    Compact,
+
+    #[serde(alias = "logfmt", alias = "Logfmt", alias = "LOGFMT")]
+    Logfmt,
+
+    #[serde(alias = "ecs", alias = "Ecs", alias = "ECS")]
+    Ecs,
+
+    #[serde(alias = "gcp", alias = "Gcp", alias = "GCP")]
+    Gcp,
+}
+
+pub fn get_otel_config(figment: &Figment) -> OtelConfig {
+    figment
+        .clone()
+        .select("otel")
+        .merge(
+            Env::prefixed("VULPO_")
+                .only(&["log_format", "log_level"])
+                .global(),
+        )
+        .extract::<OtelConfig>()
+        .expect("Otel config")
+}
+
+pub fn init(service_name: &'static str, config: &Figment) {
+    let otel_config = get_otel_config(config);
+
+    let log_levels = otel_config.log_levels.clone().unwrap_or_default();
+    let precedence = otel_config
+        .log_level_precedence
+        .clone()
+        .unwrap_or(LogLevelPrecedence::Env);
+    let directives = filter::config_directives(otel_config.log_level.as_deref(), &log_levels);
+
+    // The propagators are installed even when no exporter is configured, so the trace context
+    // still flows through services that don't export spans themselves
+    let propagators = otel_config
+        .propagators
+        .clone()
+        .unwrap_or_else(propagation::default_propagators);
+    opentelemetry::global::set_text_map_propagator(propagation::composite(&propagators));
+
+    let sampler = otel_config
+        .sample_ratio
+        .map(opentelemetry::sdk::trace::Sampler::TraceIdRatioBased)
+        .unwrap_or(opentelemetry::sdk::trace::Sampler::AlwaysOn);
+
+    let trace_config = opentelemetry::sdk::trace::config()
+        .with_sampler(sampler)
+        .with_resource(opentelemetry::sdk::Resource::new(vec![
+            opentelemetry::KeyValue::new("service.name", service_name),
+        ]));
+
+    let redactor = Arc::new(Redactor::new(
+        &otel_config.redact.clone().unwrap_or_default(),
+    ));
+    let signals = otel_config.signals.clone().unwrap_or_default();
+
+    // Without an exporter spans are still created, so trace ids end up in the logs and
+    // the incoming context is propagated to downstream services
+    let mut provider =
+        opentelemetry::sdk::trace::TracerProvider::builder().with_config(trace_config);
+
+    if let Some(addr) = otel_config
+        .address
+        .as_ref()
+        .filter(|_| signals.traces.unwrap_or(true))
+    {
+        let exporter = opentelemetry_otlp::new_exporter()
+            .tonic()
+            .with_endpoint(addr);
+        let exporter = SpanExporterBuilder::from(exporter)
+            .build_span_exporter()
+            .unwrap();
+
+        provider =
+            provider.with_batch_exporter(RedactingExporter::new(exporter, redactor.clone()), Tokio);
+    }
+
+    let provider = provider.build();
+    let tracer = provider.tracer(service_name);
+    opentelemetry::global::set_tracer_provider(provider);
+
+    if let Some(addr) = otel_config
+        .address
+        .as_ref()
+        .filter(|_| signals.metrics.unwrap_or(true))
+    {
+        let controller = opentelemetry_otlp::new_pipeline()
+            .metrics(tokio::spawn, opentelemetry::util::tokio_interval_stream)
+            .with_exporter(
+                opentelemetry_otlp::new_exporter()
+                    .tonic()
+                    .with_endpoint(addr),
+            )
+            .with_resource(vec![opentelemetry::KeyValue::new(
+                "service.name",
+                service_name,
+            )])
+            .build()
+            .unwrap();
+        *METRICS_CONTROLLER.lock().unwrap() = Some(controller);
+    }
+
+    let mut layers: Vec<Box<dyn Layer<Registry> + Send + Sync>> = Vec::new();
+    layers.push(
+        tracing_opentelemetry::layer()
+            .with_tracer(tracer)
+            .with_filter(filter::reloadable(filter::env_filter(
+                &directives,
+                &precedence,
+            )))
+            .boxed(),
+    );
+
+    if let Some(addr) = otel_config
+        .address
+        .as_ref()
+        .filter(|_| signals.logs.unwrap_or(true))
+    {
+        layers.push(
+            logs::OtlpLogLayer::new(addr, service_name, redactor.clone())
+                .with_filter(filter::reloadable(filter::env_filter(
+                    &directives,
+                    &precedence,
+                )))
+                .boxed(),
+        );
+    }
+
+    #[cfg(feature = "error-reporting")]
+    if let Some(layer) = error_reporting::layer(service_name, config, redactor.clone()) {
+        layers.push(
+            layer
+                .with_filter(filter::reloadable(filter::env_filter(
+                    &directives,
+                    &precedence,
+                )))
+                .boxed(),
+        );
+    }
+
+    // Stdout is enabled unless it's explicitly turned off
+    if otel_config.log_stdout.unwrap_or(true) {
+        let log_format = otel_config
+            .log_format
+            .as_ref()
+            .unwrap_or(&LogFormat::Default);
+        layers.push(
+            stdout_layer(log_format, &otel_config, &redactor, std::io::stdout)
+                .with_filter(filter::reloadable(filter::env_filter(
+                    &directives,
+                    &precedence,
+                )))
+                .boxed(),
+        );
+    }
+
+    if let Some(log_file) = otel_config.log_file.as_ref() {
+        let (writer, guard) = file::writer(log_file);
+        LOG_GUARDS.lock().unwrap().push(guard);
+
+        let log_format = log_file
+            .format
+            .as_ref()
+            .or(otel_config.log_format.as_ref())
+            .unwrap_or(&LogFormat::Default);
+        let file_directives = match log_file.level.as_deref() {
+            Some(level) => filter::config_directives(Some(level), &log_levels),
+            None => directives.clone(),
+        };
+
+        layers.push(
+            file_layer(log_format, &otel_config, &redactor, writer)
+                .with_filter(filter::env_filter(&file_directives, &precedence))
+                .boxed(),
+        );
+    }
+
+    tracing_subscriber::registry().with(layers).init();
+    panic::install_hook();
+}
+
+/// Flushes buffered log lines, pending spans, log records and metrics, should be awaited
+/// before the process exits
+pub async fn shutdown() {
+    LOG_GUARDS.lock().unwrap().clear();
+    logs::shutdown().await;
+    #[cfg(feature = "error-reporting")]
+    error_reporting::shutdown();
+    METRICS_CONTROLLER.lock().unwrap().take();
+    opentelemetry::global::shutdown_tracer_provider();
+}
+
+fn stdout_layer<W>(
+    format: &LogFormat,
+    config: &OtelConfig,
+    redactor: &Arc<Redactor>,
+    writer: W,
+) -> Box<dyn Layer<Registry> + Send + Sync>
+where
+    W: for<'writer> MakeWriter<'writer> + Send + Sync + 'static,
+{
+    let text_fields = RedactedFields::new(DefaultFields::new(), redactor.clone());
+    fmt_layer(format, config, redactor, writer, true, text_fields)
+}
+
+fn file_layer<W>(
+    format: &LogFormat,
+    config: &OtelConfig,
+    redactor: &Arc<Redactor>,
+    writer: W,
+) -> Box<dyn Layer<Registry> + Send + Sync>
+where
+    W: for<'writer> MakeWriter<'writer> + Send + Sync + 'static,
+{
+    let text_fields = FileFields(RedactedFields::new(DefaultFields::new(), redactor.clone()));
+    fmt_layer(format, config, redactor, writer, false, text_fields)
+}
+
+// The formatted span fields are cached per field formatter type, layers with a different
+// `ansi` setting need their own `text_fields` type
+fn fmt_layer<W, N>(
+    format: &LogFormat,
+    config: &OtelConfig,
+    redactor: &Arc<Redactor>,
+    writer: W,
+    ansi: bool,
+    text_fields: N,
+) -> Box<dyn Layer<Registry> + Send + Sync>
+where
+    W: for<'writer> MakeWriter<'writer> + Send + Sync + 'static,
+    N: for<'writer> FormatFields<'writer> + Send + Sync + 'static,
+{
+    let layer = tracing_subscriber::fmt::layer()
+        .with_writer(writer)
+        .with_ansi(ansi);
+    let json_fields = || RedactedJsonFields::new(redactor.clone());
+
+    match format {
+        LogFormat::Default => layer
+            .event_format(TextFormat::new(tracing_subscriber::fmt::format()))
+            .fmt_fields(text_fields)
+            .boxed(),
+        LogFormat::Pretty => layer
+            .event_format(TextFormat::new(tracing_subscriber::fmt::format().pretty()))
+            .fmt_fields(text_fields)
+            .boxed(),
+        LogFormat::Compact => layer
+            .event_format(TextFormat::new(tracing_subscriber::fmt::format().compact()))
+            .fmt_fields(text_fields)
+            .boxed(),
+        LogFormat::Logfmt => layer
+            .with_ansi(false)
+            .event_format(LogfmtFormat::new(redactor.clone()))
+            .fmt_fields(json_fields())
+            .boxed(),
+        LogFormat::Json => layer
+            .with_ansi(false)
+            .event_format(JsonFormat::new(JsonLayout::Default, redactor.clone()))
+            .fmt_fields(json_fields())
+            .boxed(),
+        LogFormat::Ecs => layer
+            .with_ansi(false)
+            .event_format(JsonFormat::new(JsonLayout::Ecs, redactor.clone()))
+            .fmt_fields(json_fields())
+            .boxed(),
+        LogFormat::Gcp => layer
+            .with_ansi(false)
+            .event_format(
+                JsonFormat::new(JsonLayout::Gcp, redactor.clone())
+                    .with_gcp_project_id(config.gcp_project_id.clone()),
+            )
+            .fmt_fields(json_fields())
+            .boxed(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use figment::providers::Serialized;
+    use file::Rotation;
+    use tracing::subscriber::with_default;
+
+    #[test]
+    fn file_lines_have_no_ansi_codes() {
+        let dir = std::env::temp_dir().join(format!("werkbank-ansi-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let log_file = LogFileConfig {
+            path: dir.join("app.log"),
+            rotation: Some(Rotation::Never),
+            max_size: None,
+            max_files: None,
+            format: None,
+            level: None,
+        };
+
+        let config = get_otel_config(&Figment::from(Serialized::defaults(serde_json::json!({}))));
+        let redactor = Arc::new(Redactor::new(&RedactConfig::default()));
+        let (writer, guard) = file::writer(&log_file);
+
+        // stdout formats the span fields first, with colors
+        let layers = vec![
+            stdout_layer(&LogFormat::Default, &config, &redactor, std::io::sink),
+            file_layer(&LogFormat::Default, &config, &redactor, writer),
+        ];
+        let subscriber = tracing_subscriber::registry().with(layers);
+
+        with_default(subscriber, || {
+            let span = tracing::info_span!("req", http.method = "GET");
+            let _enter = span.enter();
+            tracing::info!(status = 200, "done");
+        });
+        drop(guard);
+
+        let lines = std::fs::read_to_string(&log_file.path).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(lines.contains("http.method=\"GET\""), "{}", lines);
+        assert!(!lines.contains("\x1b["), "{}", lines);
+    }
+}
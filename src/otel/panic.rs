@@ -0,0 +1,131 @@
+use std::backtrace::Backtrace;
+use std::collections::HashMap;
+use std::panic::{self, PanicHookInfo};
+use std::sync::{LazyLock, Mutex};
+
+use opentelemetry::trace::StatusCode;
+use tokio::task;
+use tracing::{error, Level, Span};
+use tracing_opentelemetry::OtelData;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Registry;
+
+// The request spans by the task that handles the request. Rocket handles a request in one task
+// but doesn't enter the request span while the handler runs.
+static REQUEST_SPANS: LazyLock<Mutex<HashMap<task::Id, Span>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Panics of the current task are logged in `span` until the scope is dropped.
+pub(crate) struct RequestScope(task::Id);
+
+impl RequestScope {
+    /// `None` outside of a tokio task.
+    pub(crate) fn enter(span: &Span) -> Option<RequestScope> {
+        let id = task::try_id()?;
+        REQUEST_SPANS.lock().ok()?.insert(id, span.clone());
+        Some(RequestScope(id))
+    }
+}
+
+impl Drop for RequestScope {
+    fn drop(&mut self) {
+        if let Ok(mut spans) = REQUEST_SPANS.lock() {
+            spans.remove(&self.0);
+        }
+    }
+}
+
+/// Logs panics as structured error events in the request span, or the current span outside of
+/// requests, and marks the span as failed. The previously installed hook, usually the default
+/// one that prints to stderr, is only called while no subscriber records the errors.
+pub fn install_hook() {
+    let previous = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        if tracing::enabled!(Level::ERROR) {
+            log_panic(info);
+        } else {
+            previous(info);
+        }
+    }));
+}
+
+fn request_span() -> Option<Span> {
+    let id = task::try_id()?;
+    REQUEST_SPANS.lock().ok()?.get(&id).cloned()
+}
+
+fn log_panic(info: &PanicHookInfo<'_>) {
+    let message = match info.payload().downcast_ref::<&str>() {
+        Some(message) => message.to_string(),
+        None => match info.payload().downcast_ref::<String>() {
+            Some(message) => message.clone(),
+            None => "Box<dyn Any>".to_string(),
+        },
+    };
+    let backtrace = Backtrace::force_capture();
+    let thread = std::thread::current();
+
+    let span = request_span().unwrap_or_else(Span::current);
+    let request_id = span_attribute(&span, "http.request_id").unwrap_or_default();
+    set_error_status(&span, &message);
+
+    match info.location() {
+        Some(location) => error!(
+            parent: &span,
+            exception.r#type = "panic",
+            exception.message = %message,
+            exception.stacktrace = %backtrace,
+            code.filepath = %location.file(),
+            code.lineno = location.line(),
+            code.column = location.column(),
+            thread.name = thread.name().unwrap_or("<unnamed>"),
+            http.request_id = %request_id,
+            "Panicked at {}: {}",
+            location,
+            message
+        ),
+        None => error!(
+            parent: &span,
+            exception.r#type = "panic",
+            exception.message = %message,
+            exception.stacktrace = %backtrace,
+            thread.name = thread.name().unwrap_or("<unnamed>"),
+            http.request_id = %request_id,
+            "Panicked: {}",
+            message
+        ),
+    }
+}
+
+/// Looks up an attribute recorded on the span or one of its parents.
+fn span_attribute(span: &Span, key: &str) -> Option<String> {
+    span.with_subscriber(|(id, dispatch)| {
+        let registry = dispatch.downcast_ref::<Registry>()?;
+        let span = registry.span(id)?;
+
+        span.scope().find_map(|span| {
+            let extensions = span.extensions();
+            let attributes = extensions.get::<OtelData>()?.builder.attributes.as_ref()?;
+            attributes
+                .iter()
+                .find(|attribute| attribute.key.as_str() == key)
+                .map(|attribute| attribute.value.to_string())
+        })
+    })
+    .flatten()
+}
+
+fn set_error_status(span: &Span, message: &str) {
+    span.with_subscriber(|(id, dispatch)| {
+        let span = dispatch
+            .downcast_ref::<Registry>()
+            .and_then(|registry| registry.span(id));
+
+        if let Some(span) = span {
+            if let Some(data) = span.extensions_mut().get_mut::<OtelData>() {
+                data.builder.status_code = Some(StatusCode::Error);
+                data.builder.status_message = Some(format!("panicked: {}", message).into());
+            }
+        }
+    });
+}
@@ -0,0 +1,434 @@
+use opentelemetry::propagation::text_map_propagator::FieldIter;
+use opentelemetry::propagation::{Extractor, Injector, TextMapPropagator};
+use opentelemetry::sdk::propagation::{
+    BaggagePropagator, TextMapCompositePropagator, TraceContextPropagator,
+};
+use opentelemetry::trace::{SpanContext, SpanId, TraceContextExt, TraceFlags, TraceId, TraceState};
+use opentelemetry::Context;
+use serde::{Deserialize, Serialize};
+
+const B3_SINGLE_HEADER: &str = "b3";
+const B3_TRACE_ID_HEADER: &str = "x-b3-traceid";
+const B3_SPAN_ID_HEADER: &str = "x-b3-spanid";
+const B3_SAMPLED_HEADER: &str = "x-b3-sampled";
+const B3_FLAGS_HEADER: &str = "x-b3-flags";
+
+const JAEGER_HEADER: &str = "uber-trace-id";
+const JAEGER_SAMPLED_FLAG: u8 = 0x01;
+const JAEGER_DEBUG_FLAG: u8 = 0x02;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum Propagator {
+    #[serde(
+        alias = "tracecontext",
+        alias = "TraceContext",
+        alias = "trace_context"
+    )]
+    TraceContext,
+
+    #[serde(alias = "baggage", alias = "Baggage")]
+    Baggage,
+
+    #[serde(alias = "b3", alias = "B3")]
+    B3,
+
+    #[serde(alias = "b3multi", alias = "B3Multi", alias = "b3_multi")]
+    B3Multi,
+
+    #[serde(alias = "jaeger", alias = "Jaeger")]
+    Jaeger,
+}
+
+pub fn default_propagators() -> Vec<Propagator> {
+    vec![Propagator::TraceContext, Propagator::Baggage]
+}
+
+pub fn composite(propagators: &[Propagator]) -> TextMapCompositePropagator {
+    let propagators = propagators
+        .iter()
+        .map(|propagator| -> Box<dyn TextMapPropagator + Send + Sync> {
+            match propagator {
+                Propagator::TraceContext => Box::new(TraceContextPropagator::new()),
+                Propagator::Baggage => Box::new(BaggagePropagator::new()),
+                Propagator::B3 => Box::new(B3Propagator::single_header()),
+                Propagator::B3Multi => Box::new(B3Propagator::multiple_headers()),
+                Propagator::Jaeger => Box::new(JaegerPropagator::new()),
+            }
+        })
+        .collect();
+
+    TextMapCompositePropagator::new(propagators)
+}
+
+fn remote_span_context(trace_id: &str, span_id: &str, sampled: bool) -> Option<SpanContext> {
+    let trace_id = TraceId::from_hex(trace_id).ok()?;
+    let span_id = SpanId::from_hex(span_id).ok()?;
+    let flags = if sampled {
+        TraceFlags::SAMPLED
+    } else {
+        TraceFlags::default()
+    };
+
+    let span_context = SpanContext::new(trace_id, span_id, flags, true, TraceState::default());
+    span_context.is_valid().then_some(span_context)
+}
+
+/// Zipkin B3 propagation, either as the single `b3` header or as the
+/// `X-B3-*` header family. Extraction accepts both encodings.
+#[derive(Debug)]
+pub struct B3Propagator {
+    multiple_headers: bool,
+    fields: Vec<String>,
+}
+
+impl B3Propagator {
+    pub fn single_header() -> Self {
+        B3Propagator {
+            multiple_headers: false,
+            fields: vec![B3_SINGLE_HEADER.to_string()],
+        }
+    }
+
+    pub fn multiple_headers() -> Self {
+        B3Propagator {
+            multiple_headers: true,
+            fields: vec![
+                B3_TRACE_ID_HEADER.to_string(),
+                B3_SPAN_ID_HEADER.to_string(),
+                B3_SAMPLED_HEADER.to_string(),
+                B3_FLAGS_HEADER.to_string(),
+            ],
+        }
+    }
+
+    fn extract_single_header(&self, extractor: &dyn Extractor) -> Option<SpanContext> {
+        let header = extractor.get(B3_SINGLE_HEADER)?.trim();
+        let mut parts = header.split('-');
+        let trace_id = parts.next()?;
+        let span_id = parts.next()?;
+        let sampled = matches!(parts.next(), Some("1") | Some("d") | None);
+
+        remote_span_context(trace_id, span_id, sampled)
+    }
+
+    fn extract_multiple_headers(&self, extractor: &dyn Extractor) -> Option<SpanContext> {
+        let trace_id = extractor.get(B3_TRACE_ID_HEADER)?.trim();
+        let span_id = extractor.get(B3_SPAN_ID_HEADER)?.trim();
+        let debug = extractor.get(B3_FLAGS_HEADER).map(str::trim) == Some("1");
+        let sampled = match extractor.get(B3_SAMPLED_HEADER).map(str::trim) {
+            Some("1") | Some("true") => true,
+            Some(_) => debug,
+            None => true,
+        };
+
+        remote_span_context(trace_id, span_id, sampled)
+    }
+}
+
+impl TextMapPropagator for B3Propagator {
+    fn inject_context(&self, cx: &Context, injector: &mut dyn Injector) {
+        let span = cx.span();
+        let span_context = span.span_context();
+        if !span_context.is_valid() {
+            return;
+        }
+
+        let sampled = if span_context.is_sampled() { "1" } else { "0" };
+        let trace_id = format!("{:032x}", span_context.trace_id());
+        let span_id = format!("{:016x}", span_context.span_id());
+
+        if self.multiple_headers {
+            injector.set(B3_TRACE_ID_HEADER, trace_id);
+            injector.set(B3_SPAN_ID_HEADER, span_id);
+            injector.set(B3_SAMPLED_HEADER, sampled.to_string());
+        } else {
+            injector.set(
+                B3_SINGLE_HEADER,
+                format!("{}-{}-{}", trace_id, span_id, sampled),
+            );
+        }
+    }
+
+    fn extract_with_context(&self, cx: &Context, extractor: &dyn Extractor) -> Context {
+        self.extract_single_header(extractor)
+            .or_else(|| self.extract_multiple_headers(extractor))
+            .map(|span_context| cx.with_remote_span_context(span_context))
+            .unwrap_or_else(|| cx.clone())
+    }
+
+    fn fields(&self) -> FieldIter<'_> {
+        FieldIter::new(&self.fields)
+    }
+}
+
+/// Jaeger `uber-trace-id` propagation (`{trace-id}:{span-id}:{parent-span-id}:{flags}`).
+#[derive(Debug)]
+pub struct JaegerPropagator {
+    fields: Vec<String>,
+}
+
+impl JaegerPropagator {
+    pub fn new() -> Self {
+        JaegerPropagator {
+            fields: vec![JAEGER_HEADER.to_string()],
+        }
+    }
+
+    fn extract_span_context(&self, extractor: &dyn Extractor) -> Option<SpanContext> {
+        let header = extractor.get(JAEGER_HEADER)?.trim();
+        // Jaeger clients may url-encode the separators
+        let header = header.replace("%3A", ":").replace("%3a", ":");
+        let parts = header.split(':').collect::<Vec<&str>>();
+        if parts.len() != 4 {
+            return None;
+        }
+
+        let flags = u8::from_str_radix(parts[3], 16).ok()?;
+        let sampled = flags & (JAEGER_SAMPLED_FLAG | JAEGER_DEBUG_FLAG) != 0;
+
+        remote_span_context(parts[0], parts[1], sampled)
+    }
+}
+
+impl Default for JaegerPropagator {
+    fn default() -> Self {
+        JaegerPropagator::new()
+    }
+}
+
+impl TextMapPropagator for JaegerPropagator {
+    fn inject_context(&self, cx: &Context, injector: &mut dyn Injector) {
+        let span = cx.span();
+        let span_context = span.span_context();
+        if !span_context.is_valid() {
+            return;
+        }
+
+        let flags = if span_context.is_sampled() {
+            JAEGER_SAMPLED_FLAG
+        } else {
+            0
+        };
+
+        injector.set(
+            JAEGER_HEADER,
+            format!(
+                "{:032x}:{:016x}:0:{:x}",
+                span_context.trace_id(),
+                span_context.span_id(),
+                flags
+            ),
+        );
+    }
+
+    fn extract_with_context(&self, cx: &Context, extractor: &dyn Extractor) -> Context {
+        self.extract_span_context(extractor)
+            .map(|span_context| cx.with_remote_span_context(span_context))
+            .unwrap_or_else(|| cx.clone())
+    }
+
+    fn fields(&self) -> FieldIter<'_> {
+        FieldIter::new(&self.fields)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    const TRACE_ID: &str = "4bf92f3577b34da6a3ce929d0e0e4736";
+    const SPAN_ID: &str = "00f067aa0ba902b7";
+
+    fn extract(propagator: &dyn TextMapPropagator, headers: &[(&str, &str)]) -> SpanContext {
+        let headers: HashMap<String, String> = headers
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+
+        propagator.extract(&headers).span().span_context().clone()
+    }
+
+    fn expected(sampled: bool) -> SpanContext {
+        let flags = if sampled {
+            TraceFlags::SAMPLED
+        } else {
+            TraceFlags::default()
+        };
+
+        SpanContext::new(
+            TraceId::from_hex(TRACE_ID).unwrap(),
+            SpanId::from_hex(SPAN_ID).unwrap(),
+            flags,
+            true,
+            TraceState::default(),
+        )
+    }
+
+    fn round_trip(propagator: &dyn TextMapPropagator, sampled: bool) {
+        let cx = Context::new().with_remote_span_context(expected(sampled));
+        let mut headers = HashMap::new();
+        propagator.inject_context(&cx, &mut headers);
+
+        let extracted = propagator.extract(&headers);
+        assert_eq!(extracted.span().span_context(), &expected(sampled));
+    }
+
+    #[test]
+    fn b3_single_header_128_bit_trace_id() {
+        let header = format!("{}-{}-1", TRACE_ID, SPAN_ID);
+        let span_context = extract(&B3Propagator::single_header(), &[("b3", &header)]);
+
+        assert_eq!(span_context, expected(true));
+    }
+
+    #[test]
+    fn b3_single_header_64_bit_trace_id() {
+        let header = format!("a3ce929d0e0e4736-{}-1", SPAN_ID);
+        let span_context = extract(&B3Propagator::single_header(), &[("b3", &header)]);
+
+        assert!(span_context.is_valid());
+        assert_eq!(
+            span_context.trace_id(),
+            TraceId::from_hex("0000000000000000a3ce929d0e0e4736").unwrap()
+        );
+    }
+
+    #[test]
+    fn b3_single_header_with_parent_span_id() {
+        let header = format!("{}-{}-0-05e3ac9a4f6e3b90", TRACE_ID, SPAN_ID);
+        let span_context = extract(&B3Propagator::single_header(), &[("b3", &header)]);
+
+        assert_eq!(span_context, expected(false));
+    }
+
+    #[test]
+    fn b3_single_header_deny_only() {
+        let span_context = extract(&B3Propagator::single_header(), &[("b3", "0")]);
+
+        assert!(!span_context.is_valid());
+    }
+
+    #[test]
+    fn b3_single_header_debug_flag_samples() {
+        let header = format!("{}-{}-d", TRACE_ID, SPAN_ID);
+        let span_context = extract(&B3Propagator::single_header(), &[("b3", &header)]);
+
+        assert!(span_context.is_sampled());
+    }
+
+    #[test]
+    fn b3_multiple_headers_64_bit_trace_id() {
+        let span_context = extract(
+            &B3Propagator::multiple_headers(),
+            &[
+                ("x-b3-traceid", "a3ce929d0e0e4736"),
+                ("x-b3-spanid", SPAN_ID),
+                ("x-b3-sampled", "1"),
+            ],
+        );
+
+        assert!(span_context.is_valid());
+        assert!(span_context.is_sampled());
+        assert_eq!(
+            span_context.trace_id(),
+            TraceId::from_hex("a3ce929d0e0e4736").unwrap()
+        );
+    }
+
+    #[test]
+    fn b3_multiple_headers_debug_flag_samples() {
+        let span_context = extract(
+            &B3Propagator::multiple_headers(),
+            &[
+                ("x-b3-traceid", TRACE_ID),
+                ("x-b3-spanid", SPAN_ID),
+                ("x-b3-sampled", "0"),
+                ("x-b3-flags", "1"),
+            ],
+        );
+
+        assert!(span_context.is_sampled());
+    }
+
+    #[test]
+    fn b3_multiple_headers_not_sampled() {
+        let span_context = extract(
+            &B3Propagator::multiple_headers(),
+            &[
+                ("x-b3-traceid", TRACE_ID),
+                ("x-b3-spanid", SPAN_ID),
+                ("x-b3-sampled", "0"),
+            ],
+        );
+
+        assert_eq!(span_context, expected(false));
+    }
+
+    #[test]
+    fn b3_extracts_both_encodings() {
+        let header = format!("{}-{}-1", TRACE_ID, SPAN_ID);
+        let span_context = extract(&B3Propagator::multiple_headers(), &[("b3", &header)]);
+
+        assert_eq!(span_context, expected(true));
+    }
+
+    #[test]
+    fn b3_round_trip() {
+        round_trip(&B3Propagator::single_header(), true);
+        round_trip(&B3Propagator::single_header(), false);
+        round_trip(&B3Propagator::multiple_headers(), true);
+        round_trip(&B3Propagator::multiple_headers(), false);
+    }
+
+    #[test]
+    fn jaeger_128_bit_trace_id() {
+        let header = format!("{}:{}:0:1", TRACE_ID, SPAN_ID);
+        let span_context = extract(&JaegerPropagator::new(), &[("uber-trace-id", &header)]);
+
+        assert_eq!(span_context, expected(true));
+    }
+
+    #[test]
+    fn jaeger_64_bit_trace_id() {
+        let header = format!("a3ce929d0e0e4736:{}:0:1", SPAN_ID);
+        let span_context = extract(&JaegerPropagator::new(), &[("uber-trace-id", &header)]);
+
+        assert!(span_context.is_valid());
+        assert_eq!(
+            span_context.trace_id(),
+            TraceId::from_hex("a3ce929d0e0e4736").unwrap()
+        );
+    }
+
+    #[test]
+    fn jaeger_url_encoded() {
+        let header = format!("{}%3A{}%3a0%3A1", TRACE_ID, SPAN_ID);
+        let span_context = extract(&JaegerPropagator::new(), &[("uber-trace-id", &header)]);
+
+        assert_eq!(span_context, expected(true));
+    }
+
+    #[test]
+    fn jaeger_debug_flag_samples() {
+        let header = format!("{}:{}:0:2", TRACE_ID, SPAN_ID);
+        let span_context = extract(&JaegerPropagator::new(), &[("uber-trace-id", &header)]);
+
+        assert!(span_context.is_sampled());
+    }
+
+    #[test]
+    fn jaeger_malformed_header() {
+        let header = format!("{}:{}:1", TRACE_ID, SPAN_ID);
+        let span_context = extract(&JaegerPropagator::new(), &[("uber-trace-id", &header)]);
+
+        assert!(!span_context.is_valid());
+    }
+
+    #[test]
+    fn jaeger_round_trip() {
+        round_trip(&JaegerPropagator::new(), true);
+        round_trip(&JaegerPropagator::new(), false);
+    }
+}
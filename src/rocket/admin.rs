@@ -0,0 +1,111 @@
+use figment::{providers::Env, Figment};
+use rocket::fairing::{AdHoc, Fairing};
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::serde::json::Json;
+use rocket::{get, put, routes, Route};
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::otel::filter;
+
+pub const DEFAULT_BASE: &str = "/_werkbank";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AdminConfig {
+    pub enabled: Option<bool>,
+    pub base: Option<String>,
+    pub token: Option<String>,
+}
+
+pub fn get_admin_config(figment: &Figment) -> AdminConfig {
+    figment
+        .clone()
+        .select("admin")
+        .merge(Env::prefixed("VULPO_ADMIN_").global())
+        .extract::<AdminConfig>()
+        .expect("Invalid admin config")
+}
+
+pub struct Admin;
+
+impl Admin {
+    /// Mounts the admin routes when `admin.enabled` is set. Without a configured
+    /// token the routes stay unmounted, so they are never exposed unprotected.
+    pub fn fairing(figment: &Figment) -> impl Fairing {
+        let config = get_admin_config(figment);
+
+        AdHoc::on_ignite("Add admin routes", move |rocket| async move {
+            if !config.enabled.unwrap_or(false) {
+                return rocket;
+            }
+
+            if config.token.is_none() {
+                warn!("Admin: routes are enabled but no token is configured, not mounting");
+                return rocket;
+            }
+
+            let base = config.base.clone().unwrap_or(DEFAULT_BASE.to_string());
+            info!("Admin: mounted at {}", base);
+            rocket.mount(base, Admin::routes()).manage(config)
+        })
+    }
+
+    pub fn routes() -> Vec<Route> {
+        routes![get_log_level, set_log_level]
+    }
+}
+
+pub struct AdminToken;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for AdminToken {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let expected = match request
+            .rocket()
+            .state::<AdminConfig>()
+            .and_then(|config| config.token.as_ref())
+        {
+            None => return Outcome::Failure((Status::Forbidden, ())),
+            Some(token) => token,
+        };
+
+        let token = request
+            .headers()
+            .get_one("Authorization")
+            .and_then(|value| value.strip_prefix("Bearer "));
+
+        match token {
+            Some(token) if constant_time_eq(token.as_bytes(), expected.as_bytes()) => {
+                Outcome::Success(AdminToken)
+            }
+            _ => Outcome::Failure((Status::Unauthorized, ())),
+        }
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct LogLevel {
+    pub directives: String,
+}
+
+#[get("/log-level")]
+fn get_log_level(_token: AdminToken) -> Result<Json<LogLevel>, Status> {
+    filter::current_directives()
+        .map(|directives| Json(LogLevel { directives }))
+        .ok_or(Status::NotFound)
+}
+
+#[put("/log-level", data = "<body>")]
+fn set_log_level(_token: AdminToken, body: Json<LogLevel>) -> Result<Json<LogLevel>, Status> {
+    filter::set_directives(&body.directives).map_err(|_| Status::BadRequest)?;
+
+    let directives = filter::current_directives().unwrap_or(body.into_inner().directives);
+    Ok(Json(LogLevel { directives }))
+}
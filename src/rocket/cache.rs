@@ -10,6 +10,7 @@ use rocket::fairing::AdHoc;
 use rocket::fairing::Fairing;
 use rocket::request::Outcome;
 use rocket::request::{FromRequest, Request};
+use rocket::{Phase, Rocket};
 use serde::{Deserialize, Serialize};
 use std::num::NonZeroUsize;
 use std::ops::Deref;
@@ -32,7 +33,7 @@ pub fn get_cache_config(figment: &Figment) -> CacheConfig {
         .select("cache")
         .merge(Env::prefixed("VULPO_CACHE_").global())
         .extract::<CacheConfig>()
-        .unwrap_or_else(|_| CacheConfig {
+        .unwrap_or(CacheConfig {
             url: None,
             cache_size: None,
             off: Some(false),
@@ -46,6 +47,11 @@ pub trait CacheProvider {
     async fn set(&self, key: &Path, value: &str) -> Option<()>;
 
     async fn set_ex(&self, key: &Path, value: &str, ttl: Duration) -> Option<()>;
+
+    /// Checks that the cache is reachable
+    async fn ping(&self) -> Option<()> {
+        Some(())
+    }
 }
 
 pub struct Cache(Arc<dyn CacheProvider + Sync + Send>);
@@ -60,7 +66,7 @@ impl Deref for Cache {
 
 impl Cache {
     pub fn fairing(figment: &Figment) -> impl Fairing {
-        let config = get_cache_config(&figment);
+        let config = get_cache_config(figment);
 
         AdHoc::on_ignite("Add Cache", move |rocket| async move {
             if let Some(off) = config.off {
@@ -78,7 +84,7 @@ impl Cache {
 
             info!("Cache: Default use memory");
             let cache = MemoryProvider::new(config.cache_size);
-            return rocket.manage(Arc::new(cache));
+            rocket.manage(Arc::new(cache))
         })
     }
 }
@@ -88,16 +94,26 @@ impl<'r> FromRequest<'r> for Cache {
     type Error = ();
 
     async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
-        if let Some(redis) = request.rocket().state::<Arc<RedisProvider>>() {
-            return Outcome::Success(Cache(redis.clone()));
+        match provider(request.rocket()) {
+            Some(provider) => Outcome::Success(Cache(provider)),
+            None => Outcome::Success(Cache(Arc::new(NoCacheProvider))),
         }
+    }
+}
 
-        if let Some(memory) = request.rocket().state::<Arc<MemoryProvider>>() {
-            return Outcome::Success(Cache(memory.clone()));
-        }
+/// The configured cache, `None` if the cache is off.
+pub(crate) fn provider<P: Phase>(
+    rocket: &Rocket<P>,
+) -> Option<Arc<dyn CacheProvider + Sync + Send>> {
+    if let Some(redis) = rocket.state::<Arc<RedisProvider>>() {
+        return Some(redis.clone());
+    }
 
-        Outcome::Success(Cache(Arc::new(NoCacheProvider)))
+    if let Some(memory) = rocket.state::<Arc<MemoryProvider>>() {
+        return Some(memory.clone());
     }
+
+    None
 }
 
 pub struct RedisProvider {
@@ -135,6 +151,12 @@ impl CacheProvider for RedisProvider {
         let _: String = con.set_ex(key.to_str(), value, ttl).await.ok()?;
         Some(())
     }
+
+    async fn ping(&self) -> Option<()> {
+        let mut con = self.client.get_async_connection().await.ok()?;
+        let _: String = redis::cmd("PING").query_async(&mut con).await.ok()?;
+        Some(())
+    }
 }
 
 pub struct MemoryProvider {
@@ -0,0 +1,208 @@
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use figment::{providers::Env, Figment};
+use futures::future::{join_all, BoxFuture};
+use futures::FutureExt;
+use rocket::fairing::{AdHoc, Fairing};
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::serde::json::Json;
+use rocket::{get, routes, Orbit, Rocket, Route, State};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+
+use super::cache;
+
+pub const DEFAULT_BASE: &str = "/health";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct HealthConfig {
+    pub enabled: Option<bool>,
+    pub base: Option<String>,
+    /// Timeout of every readiness check in milliseconds
+    pub timeout: Option<u64>,
+}
+
+pub fn get_health_config(figment: &Figment) -> HealthConfig {
+    figment
+        .clone()
+        .select("health")
+        .merge(Env::prefixed("VULPO_HEALTH_").global())
+        .extract::<HealthConfig>()
+        .expect("Invalid health config")
+}
+
+/// A custom readiness check, e.g. for a queue or a downstream service.
+#[async_trait]
+pub trait HealthCheck: Send + Sync {
+    async fn check(&self) -> Result<(), String>;
+}
+
+/// Custom readiness checks, they are used when `HealthChecks` is managed before the health
+/// fairing is attached.
+#[derive(Default)]
+pub struct HealthChecks(Vec<(String, Arc<dyn HealthCheck>)>);
+
+impl HealthChecks {
+    pub fn new() -> HealthChecks {
+        HealthChecks::default()
+    }
+
+    pub fn add(mut self, name: &str, check: impl HealthCheck + 'static) -> HealthChecks {
+        self.0.push((name.to_string(), Arc::new(check)));
+        self
+    }
+}
+
+pub struct Health;
+
+impl Health {
+    /// Mounts `live` and `ready` at `health.base` unless `health.enabled` is false.
+    pub fn fairing(figment: &Figment) -> impl Fairing {
+        let config = get_health_config(figment);
+
+        AdHoc::on_ignite("Add health routes", move |rocket| async move {
+            if !config.enabled.unwrap_or(true) {
+                return rocket;
+            }
+
+            let rocket = match rocket.state::<HealthChecks>() {
+                Some(_) => rocket,
+                None => rocket.manage(HealthChecks::new()),
+            };
+
+            let base = config.base.clone().unwrap_or(DEFAULT_BASE.to_string());
+            rocket.mount(base, Health::routes()).manage(config)
+        })
+    }
+
+    pub fn routes() -> Vec<Route> {
+        routes![live, ready]
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthStatus {
+    Up,
+    Down,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ComponentHealth {
+    pub status: HealthStatus,
+    pub latency_ms: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct HealthReport {
+    pub status: HealthStatus,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub components: BTreeMap<String, ComponentHealth>,
+}
+
+#[get("/live")]
+fn live() -> Json<HealthReport> {
+    Json(HealthReport {
+        status: HealthStatus::Up,
+        components: BTreeMap::new(),
+    })
+}
+
+// The checked components are found in the managed state
+struct Components<'r>(&'r Rocket<Orbit>);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Components<'r> {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        Outcome::Success(Components(request.rocket()))
+    }
+}
+
+#[get("/ready")]
+async fn ready(
+    components: Components<'_>,
+    config: &State<HealthConfig>,
+    checks: &State<HealthChecks>,
+) -> (Status, Json<HealthReport>) {
+    let timeout = Duration::from_millis(config.timeout.unwrap_or(2_000));
+    let mut pending: Vec<BoxFuture<'_, (String, ComponentHealth)>> = Vec::new();
+
+    if let Some(pool) = components.0.state::<PgPool>() {
+        pending.push(
+            component("database", timeout, async move {
+                sqlx::query("select 1")
+                    .execute(pool)
+                    .await
+                    .map(|_| ())
+                    .map_err(|err| err.to_string())
+            })
+            .boxed(),
+        );
+    }
+
+    if let Some(cache) = cache::provider(components.0) {
+        pending.push(
+            component("cache", timeout, async move {
+                cache.ping().await.ok_or("ping failed".to_string())
+            })
+            .boxed(),
+        );
+    }
+
+    for (name, check) in checks.0.iter() {
+        pending.push(component(name, timeout, check.check()).boxed());
+    }
+
+    let components: BTreeMap<_, _> = join_all(pending).await.into_iter().collect();
+    let status = if components
+        .values()
+        .all(|component| component.status == HealthStatus::Up)
+    {
+        HealthStatus::Up
+    } else {
+        HealthStatus::Down
+    };
+    let code = match status {
+        HealthStatus::Up => Status::Ok,
+        HealthStatus::Down => Status::ServiceUnavailable,
+    };
+
+    (code, Json(HealthReport { status, components }))
+}
+
+async fn component(
+    name: &str,
+    timeout: Duration,
+    check: impl Future<Output = Result<(), String>>,
+) -> (String, ComponentHealth) {
+    let start = Instant::now();
+    let result = match tokio::time::timeout(timeout, check).await {
+        Ok(result) => result,
+        Err(_) => Err(format!("timed out after {:?}", timeout)),
+    };
+    let latency_ms = start.elapsed().as_secs_f64() * 1000.0;
+
+    let health = match result {
+        Ok(()) => ComponentHealth {
+            status: HealthStatus::Up,
+            latency_ms,
+            error: None,
+        },
+        Err(error) => ComponentHealth {
+            status: HealthStatus::Down,
+            latency_ms,
+            error: Some(error),
+        },
+    };
+
+    (name.to_string(), health)
+}
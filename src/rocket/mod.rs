@@ -1,9 +1,16 @@
+pub mod admin;
 pub mod cache;
 mod cors;
 pub mod db;
+pub mod health;
+pub mod project;
+pub mod request_id;
 pub mod tracing;
 
 pub use crate::rocket::tracing::TracingFairing;
+pub use admin::Admin;
 pub use cache::Cache;
 pub use cors::Cors;
-pub use db::Db;
+pub use db::{Db, DbRead, DbTx, TenantDb};
+pub use health::Health;
+pub use project::ProjectId;
@@ -0,0 +1,51 @@
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome, Request};
+use uuid::Uuid;
+
+pub const PROJECT_HEADER: &str = "Vulpo-Project";
+
+/// The project of the request from the `Vulpo-Project` header. `ProjectId` requires the header,
+/// `ProjectId<Option<Uuid>>` accepts requests without it. Both fail with 400 on a malformed id.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ProjectId<T = Uuid>(pub T);
+
+struct CachedProjectId(Result<Option<Uuid>, ()>);
+
+/// Parses the header once per request, `Err` if it isn't a UUID.
+pub(crate) fn project_id(request: &Request<'_>) -> Result<Option<Uuid>, ()> {
+    request
+        .local_cache(|| {
+            CachedProjectId(
+                request
+                    .headers()
+                    .get_one(PROJECT_HEADER)
+                    .map(|value| Uuid::parse_str(value.trim()).map_err(|_| ()))
+                    .transpose(),
+            )
+        })
+        .0
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ProjectId {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, ()> {
+        match project_id(request) {
+            Ok(Some(project_id)) => Outcome::Success(ProjectId(project_id)),
+            _ => Outcome::Failure((Status::BadRequest, ())),
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ProjectId<Option<Uuid>> {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, ()> {
+        match project_id(request) {
+            Ok(project_id) => Outcome::Success(ProjectId(project_id)),
+            Err(()) => Outcome::Failure((Status::BadRequest, ())),
+        }
+    }
+}
@@ -0,0 +1,100 @@
+use std::sync::Arc;
+
+use opentelemetry::trace::TraceContextExt;
+use opentelemetry::Context;
+use serde::{Deserialize, Serialize};
+use ulid::Ulid;
+use uuid::Uuid;
+
+pub const DEFAULT_REQUEST_ID_HEADER: &str = "X-Request-Id";
+
+/// Creates the id of requests that don't bring a usable one. A custom generator is used when
+/// it's managed as `Arc<dyn RequestIdGenerator>` before the tracing fairing is attached.
+pub trait RequestIdGenerator: Send + Sync {
+    /// `context` holds the request span, it's empty for requests that aren't traced.
+    fn generate(&self, context: &Context) -> String;
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum RequestIdStrategy {
+    #[serde(
+        alias = "uuid_v4",
+        alias = "uuidv4",
+        alias = "UuidV4",
+        alias = "UUIDV4"
+    )]
+    UuidV4,
+
+    /// Time sortable UUIDs
+    #[serde(
+        alias = "uuid_v7",
+        alias = "uuidv7",
+        alias = "UuidV7",
+        alias = "UUIDV7"
+    )]
+    UuidV7,
+
+    /// Time sortable, 26 characters in Crockford's base32
+    #[serde(alias = "ulid", alias = "Ulid", alias = "ULID")]
+    Ulid,
+
+    /// The trace id of the request span, so logs and traces share one id
+    #[serde(
+        alias = "trace_id",
+        alias = "traceid",
+        alias = "TraceId",
+        alias = "TRACE_ID"
+    )]
+    TraceId,
+}
+
+impl RequestIdStrategy {
+    pub fn generator(&self) -> Arc<dyn RequestIdGenerator> {
+        match self {
+            RequestIdStrategy::UuidV4 => Arc::new(UuidV4Generator),
+            RequestIdStrategy::UuidV7 => Arc::new(UuidV7Generator),
+            RequestIdStrategy::Ulid => Arc::new(UlidGenerator),
+            RequestIdStrategy::TraceId => Arc::new(TraceIdGenerator),
+        }
+    }
+}
+
+pub struct UuidV4Generator;
+
+impl RequestIdGenerator for UuidV4Generator {
+    fn generate(&self, _context: &Context) -> String {
+        Uuid::new_v4().to_string()
+    }
+}
+
+pub struct UuidV7Generator;
+
+impl RequestIdGenerator for UuidV7Generator {
+    fn generate(&self, _context: &Context) -> String {
+        Uuid::now_v7().to_string()
+    }
+}
+
+pub struct UlidGenerator;
+
+impl RequestIdGenerator for UlidGenerator {
+    fn generate(&self, _context: &Context) -> String {
+        Ulid::new().to_string()
+    }
+}
+
+/// Uses the trace id and falls back to a UUIDv4 when the request isn't traced.
+pub struct TraceIdGenerator;
+
+impl RequestIdGenerator for TraceIdGenerator {
+    fn generate(&self, context: &Context) -> String {
+        let span = context.span();
+        let span_context = span.span_context();
+
+        if span_context.is_valid() {
+            format!("{:032x}", span_context.trace_id())
+        } else {
+            Uuid::new_v4().to_string()
+        }
+    }
+}
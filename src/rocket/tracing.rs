@@ -1,13 +1,36 @@
-use rocket::http::Status;
+//! Request spans and request ids for Rocket.
+//!
+//! The request spans follow the OpenTelemetry HTTP server conventions except for
+//! `http.flavor`: Rocket 0.5.0-rc.2 doesn't expose the HTTP version of a request, so the
+//! attribute isn't recorded rather than guessed.
+
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::Instant;
+
+use figment::{providers::Env, Figment};
+use ipnet::IpNet;
+use opentelemetry::propagation::{Extractor, Injector};
+use rocket::fairing::AdHoc;
+use rocket::http::{Header, HeaderMap, Status};
 use rocket::request::FromRequest;
 use rocket::request::Outcome;
 use rocket::{
     fairing::{Fairing, Info, Kind},
     Data, Request, Response,
 };
-use tracing::{error, info, info_span, Span};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, error, info, info_span, trace, warn, Span};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
 use uuid::Uuid;
 
+use crate::otel::panic::RequestScope;
+
+use super::project;
+use super::request_id::{RequestIdGenerator, RequestIdStrategy, DEFAULT_REQUEST_ID_HEADER};
+
+const DEFAULT_REQUEST_ID_MAX_LENGTH: usize = 128;
+
 #[derive(Clone, Debug)]
 pub struct RequestId<T = String>(pub T);
 
@@ -16,7 +39,7 @@ impl<'r> FromRequest<'r> for RequestId {
     type Error = ();
 
     async fn from_request(request: &'r Request<'_>) -> Outcome<Self, ()> {
-        match &*request.local_cache(|| RequestId::<Option<String>>(None)) {
+        match request.local_cache(|| RequestId::<Option<String>>(None)) {
             RequestId(Some(request_id)) => Outcome::Success(RequestId(request_id.to_owned())),
             RequestId(None) => Outcome::Failure((Status::InternalServerError, ())),
         }
@@ -24,10 +47,133 @@ impl<'r> FromRequest<'r> for RequestId {
 }
 
 #[derive(Clone)]
-pub struct TracingSpan<T = Span>(T);
+pub struct TracingSpan<T = Span>(pub T);
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct TracingConfig {
+    /// Proxies whose `X-Forwarded-For` and `X-Forwarded-Proto` headers are trusted, as CIDR ranges
+    pub trusted_proxies: Option<Vec<IpNet>>,
+    /// Paths that are neither traced nor logged, a trailing `*` matches any suffix
+    pub skip_paths: Option<Vec<String>>,
+    /// Level of the log line for 2xx and 3xx responses, `Info` by default
+    pub success_level: Option<RequestLogLevel>,
+    /// Level of the log line for 4xx responses, `Warn` by default
+    pub client_error_level: Option<RequestLogLevel>,
+    /// Level of the log line for 5xx responses, `Error` by default
+    pub server_error_level: Option<RequestLogLevel>,
+    /// Share of the 2xx and 3xx responses that are logged, between 0.0 and 1.0
+    pub success_sample_rate: Option<f64>,
+    /// Longest accepted `X-Request-Id`, 128 characters by default
+    pub request_id_max_length: Option<usize>,
+    /// Only accept request ids that are UUIDs
+    pub request_id_uuid_only: Option<bool>,
+    /// Sources whose request ids are used, as CIDR ranges. Every source is trusted when unset,
+    /// request ids of other sources are replaced and recorded as `http.client_request_id`
+    pub trusted_request_id_sources: Option<Vec<IpNet>>,
+    /// Header the request id is read from and returned in, `X-Request-Id` by default
+    pub request_id_header: Option<String>,
+    /// How missing request ids are generated, `UuidV4` by default
+    pub request_id_generator: Option<RequestIdStrategy>,
+}
+
+impl TracingConfig {
+    pub(crate) fn request_id_header(&self) -> &str {
+        self.request_id_header
+            .as_deref()
+            .unwrap_or(DEFAULT_REQUEST_ID_HEADER)
+    }
+
+    fn request_id_generator(&self) -> Arc<dyn RequestIdGenerator> {
+        self.request_id_generator
+            .unwrap_or(RequestIdStrategy::UuidV4)
+            .generator()
+    }
+
+    fn is_valid_request_id(&self, request_id: &str) -> bool {
+        if self.request_id_uuid_only.unwrap_or(false) {
+            return Uuid::parse_str(request_id).is_ok();
+        }
+
+        let max_length = self
+            .request_id_max_length
+            .unwrap_or(DEFAULT_REQUEST_ID_MAX_LENGTH);
+
+        !request_id.is_empty()
+            && request_id.len() <= max_length
+            && request_id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | ':'))
+    }
+
+    fn is_trusted_request_id_source(&self, ip: Option<IpAddr>) -> bool {
+        match &self.trusted_request_id_sources {
+            None => true,
+            Some(sources) => ip.is_some_and(|ip| sources.iter().any(|source| source.contains(&ip))),
+        }
+    }
+
+    fn is_skipped(&self, path: &str) -> bool {
+        self.skip_paths
+            .iter()
+            .flatten()
+            .any(|skip| match skip.strip_suffix('*') {
+                Some(prefix) => path.starts_with(prefix),
+                None => path == skip,
+            })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum RequestLogLevel {
+    #[serde(alias = "off", alias = "Off", alias = "OFF")]
+    Off,
+
+    #[serde(alias = "trace", alias = "Trace", alias = "TRACE")]
+    Trace,
+
+    #[serde(alias = "debug", alias = "Debug", alias = "DEBUG")]
+    Debug,
+
+    #[serde(alias = "info", alias = "Info", alias = "INFO")]
+    Info,
+
+    #[serde(alias = "warn", alias = "Warn", alias = "WARN")]
+    Warn,
+
+    #[serde(alias = "error", alias = "Error", alias = "ERROR")]
+    Error,
+}
+
+struct RequestStart(Option<Instant>);
+
+pub fn get_tracing_config(figment: &Figment) -> TracingConfig {
+    figment
+        .clone()
+        .select("tracing")
+        .merge(Env::prefixed("VULPO_TRACING_").global())
+        .extract::<TracingConfig>()
+        .expect("Invalid tracing config")
+}
 
 pub struct TracingFairing;
 
+impl TracingFairing {
+    /// Attaches the fairing with the `[tracing]` config, attaching `TracingFairing` directly uses
+    /// the defaults.
+    pub fn fairing(figment: &Figment) -> impl Fairing {
+        let config = get_tracing_config(figment);
+
+        AdHoc::on_ignite("Add tracing", move |rocket| async move {
+            let rocket = match rocket.state::<Arc<dyn RequestIdGenerator>>() {
+                Some(_) => rocket,
+                None => rocket.manage(config.request_id_generator()),
+            };
+
+            rocket.manage(config).attach(TracingFairing)
+        })
+    }
+}
+
 #[rocket::async_trait]
 impl Fairing for TracingFairing {
     fn info(&self) -> Info {
@@ -37,63 +183,137 @@ impl Fairing for TracingFairing {
         }
     }
     async fn on_request(&self, req: &mut Request<'_>, _data: &mut Data<'_>) {
-        let project_id = req.headers().get_one("Vulpo-Project").unwrap_or("");
         let host = req.headers().get_one("Host").unwrap_or("");
         let user_agent = req.headers().get_one("User-Agent").unwrap_or("");
-        let request_id = req
+
+        let default_config = TracingConfig::default();
+        let config = req
+            .rocket()
+            .state::<TracingConfig>()
+            .unwrap_or(&default_config);
+        let peer_ip = peer_ip(req, config);
+
+        let header = config.request_id_header();
+        let generator = req
+            .rocket()
+            .state::<Arc<dyn RequestIdGenerator>>()
+            .cloned()
+            .unwrap_or_else(|| config.request_id_generator());
+
+        // Invalid ids are dropped, valid ids from untrusted sources are only kept for reference
+        let client_request_id = req
             .headers()
-            .get_one("X-Request-Id")
-            .map(ToString::to_string)
-            .unwrap_or_else(|| Uuid::new_v4().to_string());
+            .get_one(header)
+            .map(str::trim)
+            .filter(|request_id| config.is_valid_request_id(request_id));
+        let (request_id, client_request_id) = match client_request_id {
+            Some(request_id) if config.is_trusted_request_id_source(peer_ip) => {
+                (Some(request_id.to_string()), None)
+            }
+            client_request_id => (None, client_request_id.map(ToString::to_string)),
+        };
 
-        req.local_cache(|| RequestId(Some(request_id.to_owned())));
+        req.local_cache(|| RequestStart(Some(Instant::now())));
 
         let method = req.method().to_string();
         let path = req.uri().path().to_string();
+        let peer_ip = peer_ip.map(|ip| ip.to_string()).unwrap_or_default();
+        let scheme = scheme(req, config);
 
-        let mut name = String::with_capacity(method.len() + path.len() + 1);
-        name.push_str(&method);
-        name.push_str(" ");
-        name.push_str(&path);
+        // Skipped requests get a disabled span, so the `TracingSpan` guard still works
+        if config.is_skipped(&path) {
+            let request_id =
+                request_id.unwrap_or_else(|| generator.generate(&opentelemetry::Context::new()));
+            req.local_cache(|| RequestId(Some(request_id)));
+            req.local_cache(|| TracingSpan::<Option<Span>>(Some(Span::none())));
+            return;
+        }
+
+        // The route is only known after routing, until then the span is named as unmatched
+        let name = format!("{} <unmatched>", method);
 
+        // No `http.flavor`, Rocket doesn't expose the HTTP version, see the module docs
         let span = info_span!(
             "HTTP Request",
-            otel.name = %"HTTP Request",
+            otel.name = %name,
             server = %"rocket",
             http.method = %method,
-            http.request_id = %request_id,
+            http.request_id = tracing::field::Empty,
+            http.client_request_id = tracing::field::Empty,
             http.uri = %path,
+            http.route = tracing::field::Empty,
             http.user_agent = %user_agent,
             http.status_code = tracing::field::Empty,
             http.host = %host,
-            vulpo.project_id = %project_id
+            http.scheme = %scheme,
+            http.response_content_length = tracing::field::Empty,
+            net.peer.ip = %peer_ip,
+            otel.status_code = tracing::field::Empty,
+            vulpo.project_id = tracing::field::Empty
         );
 
+        let parent_context = opentelemetry::global::get_text_map_propagator(|propagator| {
+            propagator.extract(&HeaderExtractor(req.headers()))
+        });
+        span.set_parent(parent_context);
+
+        // Generated after the parent is set, so trace id based ids match the trace
+        let request_id = request_id.unwrap_or_else(|| generator.generate(&span.context()));
+        span.record("http.request_id", request_id.as_str());
+        // Malformed project ids are rejected by the `ProjectId` guard and not recorded
+        if let Ok(Some(project_id)) = project::project_id(req) {
+            span.record("vulpo.project_id", project_id.to_string().as_str());
+        }
+        if let Some(client_request_id) = client_request_id {
+            span.record("http.client_request_id", client_request_id.as_str());
+        }
+
+        req.local_cache(|| RequestId(Some(request_id)));
+        req.local_cache(|| RequestScope::enter(&span));
         req.local_cache(|| TracingSpan::<Option<Span>>(Some(span)));
     }
 
     async fn on_response<'r>(&self, req: &'r Request<'_>, res: &mut Response<'r>) {
+        let content_length = res.body_mut().size().await;
+
         if let Some(span) = req
             .local_cache(|| TracingSpan::<Option<Span>>(None))
             .0
             .to_owned()
         {
+            let context = span.context();
+            opentelemetry::global::get_text_map_propagator(|propagator| {
+                propagator.inject_context(&context, &mut HeaderInjector(res))
+            });
+
             let _entered_span = span.entered();
-            _entered_span.record("http.status_code", &res.status().code);
+            _entered_span.record("http.status_code", res.status().code);
+            if let Some(route) = req.route() {
+                let name = format!("{} {}", req.method(), route.uri.path());
+                _entered_span.record("otel.name", name.as_str());
+                _entered_span.record("http.route", route.uri.path());
+            }
+            if let Some(content_length) = content_length {
+                _entered_span.record("http.response_content_length", content_length);
+            }
+            if res.status().code >= 500 {
+                _entered_span.record("otel.status_code", "ERROR");
+            }
 
             if let Some(request_id) = &req.local_cache(|| RequestId::<Option<String>>(None)).0 {
-                if res.status().code > 299 {
-                    error!("Returning error {} with {}", request_id, res.status());
-                } else {
-                    info!("Returning success {} with {}", request_id, res.status());
-                }
+                log_response(req, res.status(), request_id);
             }
 
             drop(_entered_span);
         }
 
         if let Some(request_id) = &req.local_cache(|| RequestId::<Option<String>>(None)).0 {
-            res.set_raw_header("X-Request-Id", request_id);
+            let header = req
+                .rocket()
+                .state::<TracingConfig>()
+                .map(TracingConfig::request_id_header)
+                .unwrap_or(DEFAULT_REQUEST_ID_HEADER);
+            res.set_raw_header(header.to_string(), request_id.to_string());
         }
     }
 }
@@ -103,9 +323,341 @@ impl<'r> FromRequest<'r> for TracingSpan {
     type Error = ();
 
     async fn from_request(request: &'r Request<'_>) -> Outcome<Self, ()> {
-        match &*request.local_cache(|| TracingSpan::<Option<Span>>(None)) {
+        match request.local_cache(|| TracingSpan::<Option<Span>>(None)) {
             TracingSpan(Some(span)) => Outcome::Success(TracingSpan(span.to_owned())),
             TracingSpan(None) => Outcome::Failure((Status::InternalServerError, ())),
         }
     }
 }
+
+fn log_response(req: &Request<'_>, status: Status, request_id: &str) {
+    let default_config = TracingConfig::default();
+    let config = req
+        .rocket()
+        .state::<TracingConfig>()
+        .unwrap_or(&default_config);
+
+    if config.is_skipped(req.uri().path().as_str()) {
+        return;
+    }
+
+    let level = match status.code {
+        500.. => config.server_error_level.unwrap_or(RequestLogLevel::Error),
+        400..=499 => config.client_error_level.unwrap_or(RequestLogLevel::Warn),
+        _ => {
+            let sample_rate = config.success_sample_rate.unwrap_or(1.0);
+            if sample_rate < 1.0 && rand::random::<f64>() >= sample_rate {
+                return;
+            }
+            config.success_level.unwrap_or(RequestLogLevel::Info)
+        }
+    };
+
+    let duration_ms = req
+        .local_cache(|| RequestStart(None))
+        .0
+        .map(|start| start.elapsed().as_secs_f64() * 1000.0)
+        .unwrap_or_default();
+    let outcome = match status.code {
+        500.. => "server error",
+        400..=499 => "client error",
+        _ => "success",
+    };
+
+    match level {
+        RequestLogLevel::Off => {}
+        RequestLogLevel::Trace => trace!(
+            duration_ms,
+            "Returning {} {} with {} in {:.2}ms",
+            outcome,
+            request_id,
+            status,
+            duration_ms
+        ),
+        RequestLogLevel::Debug => debug!(
+            duration_ms,
+            "Returning {} {} with {} in {:.2}ms", outcome, request_id, status, duration_ms
+        ),
+        RequestLogLevel::Info => info!(
+            duration_ms,
+            "Returning {} {} with {} in {:.2}ms", outcome, request_id, status, duration_ms
+        ),
+        RequestLogLevel::Warn => warn!(
+            duration_ms,
+            "Returning {} {} with {} in {:.2}ms", outcome, request_id, status, duration_ms
+        ),
+        RequestLogLevel::Error => error!(
+            duration_ms,
+            "Returning {} {} with {} in {:.2}ms", outcome, request_id, status, duration_ms
+        ),
+    }
+}
+
+fn is_trusted(ip: &IpAddr, config: &TracingConfig) -> bool {
+    config
+        .trusted_proxies
+        .iter()
+        .flatten()
+        .any(|proxy| proxy.contains(ip))
+}
+
+/// The client address, `X-Forwarded-For` is followed from the right as long as the
+/// addresses belong to trusted proxies.
+fn peer_ip(req: &Request<'_>, config: &TracingConfig) -> Option<IpAddr> {
+    let remote = req.remote()?.ip();
+    if !is_trusted(&remote, config) {
+        return Some(remote);
+    }
+
+    let forwarded = req
+        .headers()
+        .get("X-Forwarded-For")
+        .flat_map(|value| value.split(','))
+        .filter_map(|ip| ip.trim().parse::<IpAddr>().ok())
+        .collect::<Vec<IpAddr>>();
+
+    let client = forwarded
+        .iter()
+        .rev()
+        .find(|ip| !is_trusted(ip, config))
+        .or_else(|| forwarded.first())
+        .copied();
+
+    Some(client.unwrap_or(remote))
+}
+
+fn scheme(req: &Request<'_>, config: &TracingConfig) -> String {
+    let forwarded_proto = req
+        .remote()
+        .filter(|remote| is_trusted(&remote.ip(), config))
+        .and_then(|_| req.headers().get_one("X-Forwarded-Proto"));
+
+    match forwarded_proto {
+        Some(proto) => proto.trim().to_ascii_lowercase(),
+        None if req.rocket().config().tls_enabled() => "https".to_string(),
+        None => "http".to_string(),
+    }
+}
+
+struct HeaderExtractor<'a>(&'a HeaderMap<'a>);
+
+impl<'a> Extractor for HeaderExtractor<'a> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get_one(key)
+    }
+
+    // Rocket's `HeaderMap` doesn't hand out borrowed header names and none of the
+    // supported propagators enumerate keys
+    fn keys(&self) -> Vec<&str> {
+        Vec::new()
+    }
+}
+
+struct HeaderInjector<'a, 'r>(&'a mut Response<'r>);
+
+impl<'a, 'r> Injector for HeaderInjector<'a, 'r> {
+    fn set(&mut self, key: &str, value: String) {
+        // The trace context propagator sets an empty `tracestate` when there is none
+        if !value.is_empty() {
+            self.0.set_header(Header::new(key.to_string(), value));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use rocket::http::Header;
+    use rocket::local::blocking::Client;
+
+    use super::*;
+
+    const PROXY: &str = "10.0.0.2:443";
+    const CLIENT: &str = "203.0.113.9:51000";
+
+    fn config(trusted_proxies: &[&str], trusted_request_id_sources: &[&str]) -> TracingConfig {
+        let nets = |nets: &[&str]| nets.iter().map(|net| net.parse().unwrap()).collect();
+        TracingConfig {
+            trusted_proxies: Some(nets(trusted_proxies)),
+            trusted_request_id_sources: Some(nets(trusted_request_id_sources)),
+            ..TracingConfig::default()
+        }
+    }
+
+    // The route attribute exports a `uri!` macro, nothing uses it in the tests
+    #[allow(unused_imports)]
+    mod routes {
+        use super::RequestId;
+
+        #[rocket::get("/")]
+        pub fn request_id(request_id: RequestId) -> String {
+            request_id.0
+        }
+    }
+
+    fn client(config: TracingConfig) -> Client {
+        let rocket = rocket::build()
+            .manage(config)
+            .attach(TracingFairing)
+            .mount("/", rocket::routes![routes::request_id]);
+        Client::untracked(rocket).unwrap()
+    }
+
+    fn peer_ip_of(config: &TracingConfig, remote: &str, forwarded_for: &[&str]) -> Option<IpAddr> {
+        let client = client(config.clone());
+        let mut request = client
+            .get("/")
+            .remote(remote.parse::<SocketAddr>().unwrap());
+        for forwarded_for in forwarded_for {
+            request = request.header(Header::new("X-Forwarded-For", forwarded_for.to_string()));
+        }
+        peer_ip(request.inner(), config)
+    }
+
+    fn returned_request_id(config: TracingConfig, remote: &str, request_id: &str) -> String {
+        let client = client(config);
+        let response = client
+            .get("/")
+            .remote(remote.parse::<SocketAddr>().unwrap())
+            .header(Header::new(
+                DEFAULT_REQUEST_ID_HEADER,
+                request_id.to_string(),
+            ))
+            .dispatch();
+
+        let header = response
+            .headers()
+            .get_one(DEFAULT_REQUEST_ID_HEADER)
+            .unwrap()
+            .to_string();
+        assert_eq!(response.into_string().unwrap(), header);
+        header
+    }
+
+    #[test]
+    fn validates_request_ids() {
+        let config = TracingConfig::default();
+
+        assert!(config.is_valid_request_id("67e55044-10b1-426f-9247-bb680e5fe0c8"));
+        assert!(config.is_valid_request_id("edge:req_42.a"));
+        assert!(config.is_valid_request_id(&"a".repeat(DEFAULT_REQUEST_ID_MAX_LENGTH)));
+        assert!(!config.is_valid_request_id(&"a".repeat(DEFAULT_REQUEST_ID_MAX_LENGTH + 1)));
+        assert!(!config.is_valid_request_id(""));
+        assert!(!config.is_valid_request_id("id with spaces"));
+        assert!(!config.is_valid_request_id("id\r\nX-Injected: 1"));
+        assert!(!config.is_valid_request_id("ïd"));
+    }
+
+    #[test]
+    fn validates_request_ids_with_config() {
+        let short = TracingConfig {
+            request_id_max_length: Some(4),
+            ..TracingConfig::default()
+        };
+        assert!(short.is_valid_request_id("abcd"));
+        assert!(!short.is_valid_request_id("abcde"));
+
+        let uuid_only = TracingConfig {
+            request_id_uuid_only: Some(true),
+            ..TracingConfig::default()
+        };
+        assert!(uuid_only.is_valid_request_id("67e55044-10b1-426f-9247-bb680e5fe0c8"));
+        assert!(!uuid_only.is_valid_request_id("req-42"));
+    }
+
+    #[test]
+    fn checks_request_id_sources() {
+        let ip = |ip: &str| Some(ip.parse::<IpAddr>().unwrap());
+
+        let everyone = TracingConfig::default();
+        assert!(everyone.is_trusted_request_id_source(ip("203.0.113.9")));
+        assert!(everyone.is_trusted_request_id_source(None));
+
+        let internal = config(&[], &["10.0.0.0/8", "fd00::/8"]);
+        assert!(internal.is_trusted_request_id_source(ip("10.1.2.3")));
+        assert!(internal.is_trusted_request_id_source(ip("fd12::1")));
+        assert!(!internal.is_trusted_request_id_source(ip("203.0.113.9")));
+        assert!(!internal.is_trusted_request_id_source(None));
+    }
+
+    #[test]
+    fn ignores_forwarded_for_from_untrusted_peers() {
+        let config = config(&["10.0.0.0/8"], &[]);
+
+        assert_eq!(
+            peer_ip_of(&config, CLIENT, &["10.0.0.7"]),
+            Some("203.0.113.9".parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn follows_forwarded_for_through_trusted_proxies() {
+        let config = config(&["10.0.0.0/8"], &[]);
+
+        assert_eq!(
+            peer_ip_of(&config, PROXY, &["198.51.100.1, 10.0.0.3"]),
+            Some("198.51.100.1".parse().unwrap())
+        );
+        // A client can prepend anything, only the address the proxy appended is used
+        assert_eq!(
+            peer_ip_of(&config, PROXY, &["192.0.2.66, 198.51.100.1", "10.0.0.3"]),
+            Some("198.51.100.1".parse().unwrap())
+        );
+        assert_eq!(
+            peer_ip_of(&config, PROXY, &["not-an-ip, 198.51.100.1"]),
+            Some("198.51.100.1".parse().unwrap())
+        );
+        assert_eq!(
+            peer_ip_of(&config, PROXY, &["10.0.0.4, 10.0.0.3"]),
+            Some("10.0.0.4".parse().unwrap())
+        );
+        assert_eq!(
+            peer_ip_of(&config, PROXY, &[]),
+            Some("10.0.0.2".parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn keeps_request_ids_from_trusted_sources() {
+        let config = config(&[], &["203.0.113.0/24"]);
+
+        assert_eq!(returned_request_id(config, CLIENT, "req-42"), "req-42");
+    }
+
+    #[test]
+    fn replaces_request_ids_from_untrusted_sources() {
+        let config = config(&[], &["10.0.0.0/8"]);
+
+        let request_id = returned_request_id(config, CLIENT, "req-42");
+        assert_ne!(request_id, "req-42");
+        assert!(Uuid::parse_str(&request_id).is_ok());
+    }
+
+    #[test]
+    fn replaces_request_ids_behind_spoofed_forwarded_for() {
+        let config = config(&["10.0.0.0/8"], &["10.0.0.0/8"]);
+        let client = client(config);
+
+        let response = client
+            .get("/")
+            .remote(CLIENT.parse().unwrap())
+            .header(Header::new("X-Forwarded-For", "10.0.0.7"))
+            .header(Header::new(DEFAULT_REQUEST_ID_HEADER, "req-42"))
+            .dispatch();
+
+        assert_ne!(response.into_string().unwrap(), "req-42");
+    }
+
+    #[test]
+    fn replaces_invalid_request_ids() {
+        let config = TracingConfig::default();
+        let oversized = "a".repeat(DEFAULT_REQUEST_ID_MAX_LENGTH + 1);
+
+        for request_id in [oversized.as_str(), "req 42", "req<42>"] {
+            let returned = returned_request_id(config.clone(), CLIENT, request_id);
+            assert_ne!(returned, request_id);
+            assert!(Uuid::parse_str(&returned).is_ok());
+        }
+    }
+}